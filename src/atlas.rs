@@ -0,0 +1,243 @@
+//! Block textures, packed into a single image so every block can be drawn with one texture bound
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use image::{Rgba, RgbaImage};
+use block::{Appearance, BlockType};
+use block_registry::BlockRegistry;
+use color::Color;
+
+/// Where texture tiles are read from, relative to the working directory
+pub const DEFAULT_PATH: &str = "textures";
+
+/// Texture coordinates of a tile - left, bottom, right and top - with v pointing up as in OpenGL
+pub type Uv = [f32; 4];
+
+/// Tiles of the same square size laid out in a grid, each named after the file it came from
+///
+/// The first tile is always plain white, so faces which are just a colour can sample the atlas
+/// like any other.
+pub struct TextureAtlas {
+    image: RgbaImage,
+    tile_size: u32,
+    tiles: HashMap<String, Uv>,
+    white: Uv,
+}
+
+impl TextureAtlas {
+    /// Pack `tiles` into an atlas - they must all be square and the same size
+    pub fn new(tiles: Vec<(String, RgbaImage)>) -> Result<TextureAtlas, String> {
+        let tile_size = tiles.first().map_or(1, |(_, tile)| tile.width());
+        for (name, tile) in tiles.iter() {
+            if tile.dimensions() != (tile_size, tile_size) {
+                return Err(format!("tile {} is {}x{} - every tile must be {}x{}", name, tile.width(), tile.height(), tile_size, tile_size));
+            }
+        }
+        // the white tile, then the rest in a square grid
+        let count = tiles.len() as u32 + 1;
+        let columns = (count as f32).sqrt().ceil() as u32;
+        let rows = count.div_ceil(columns);
+        let mut image = RgbaImage::new(columns * tile_size, rows * tile_size);
+        let uv = |index: u32| {
+            let (column, row) = (index % columns, index / columns);
+            // half a texel in from the edge, so nearest filtering never picks up a neighbouring tile
+            let inset = 0.5 / tile_size as f32;
+            let (width, height) = (columns as f32, rows as f32);
+            [
+                (column as f32 + inset) / width,
+                1.0 - (row as f32 + 1.0 - inset) / height,
+                (column as f32 + 1.0 - inset) / width,
+                1.0 - (row as f32 + inset) / height,
+            ]
+        };
+        for y in 0..tile_size {
+            for x in 0..tile_size {
+                image.put_pixel(x, y, Rgba([255, 255, 255, 255]));
+            }
+        }
+        let mut uvs = HashMap::new();
+        for (i, (name, tile)) in tiles.into_iter().enumerate() {
+            let index = i as u32 + 1;
+            let (left, top) = ((index % columns) * tile_size, (index / columns) * tile_size);
+            for (x, y, pixel) in tile.enumerate_pixels() {
+                image.put_pixel(left + x, top + y, *pixel);
+            }
+            uvs.insert(name, uv(index));
+        }
+        Ok(TextureAtlas { image, tile_size, tiles: uvs, white: uv(0) })
+    }
+
+    /// Pack every PNG in a directory, named after the file without its extension - a missing
+    /// directory just gives an atlas with no textures
+    pub fn from_directory<P: AsRef<Path>>(path: P) -> Result<TextureAtlas, String> {
+        let path = path.as_ref();
+        let entries = match fs::read_dir(path) {
+            Ok(entries) => entries,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return TextureAtlas::new(Vec::new()),
+            Err(e) => return Err(format!("couldn't read {}: {}", path.display(), e)),
+        };
+        let mut files = Vec::new();
+        for entry in entries {
+            let file = entry.map_err(|e| format!("couldn't read {}: {}", path.display(), e))?.path();
+            if file.extension().is_some_and(|extension| extension == "png") {
+                files.push(file);
+            }
+        }
+        // sorted, so the atlas is laid out the same way every time
+        files.sort();
+        let mut tiles = Vec::new();
+        for file in files {
+            let name = file.file_stem().and_then(|stem| stem.to_str())
+                .ok_or_else(|| format!("invalid tile name {}", file.display()))?;
+            let tile = ::image::open(&file).map_err(|e| format!("couldn't read tile {}: {}", file.display(), e))?.to_rgba8();
+            tiles.push((name.to_owned(), tile));
+        }
+        TextureAtlas::new(tiles)
+    }
+
+    /// The whole atlas, with the first tile in the top left corner
+    pub fn image(&self) -> &RgbaImage {
+        &self.image
+    }
+
+    #[allow(dead_code)]
+    pub fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+
+    /// How many mipmap levels below the full size atlas keep every tile apart - each level halves
+    /// the tiles, and once a tile is an odd number of texels across the next level would average
+    /// it with its neighbours. Tile edges line up with texels at every level up to this one, so
+    /// nearest filtering at the half texel inset never picks up a neighbouring tile.
+    pub fn mipmap_levels(&self) -> u32 {
+        self.tile_size.trailing_zeros()
+    }
+
+    pub fn uv(&self, name: &str) -> Option<Uv> {
+        self.tiles.get(name).cloned()
+    }
+
+    /// The colour and texture coordinates to draw a face with - textures aren't tinted, and a
+    /// texture missing from the atlas falls back to the block's colour
+    pub fn face(&self, appearance: Option<Appearance>, block_type: &BlockType) -> (Color, Uv) {
+        match appearance {
+            Some(Appearance::Color(color)) => (color, self.white),
+            Some(Appearance::Texture(name)) => match self.uv(name) {
+                Some(uv) => ([1.0, 1.0, 1.0], uv),
+                None => (block_type.color, self.white),
+            },
+            None => (block_type.color, self.white),
+        }
+    }
+
+    /// Names of textures used by registered blocks but missing from the atlas, sorted
+    pub fn missing(&self, blocks: &BlockRegistry) -> Vec<&'static str> {
+        let mut missing: Vec<_> = blocks.iter()
+            .flat_map(|(_, block_type)| vec![block_type.faces.top, block_type.faces.bottom, block_type.faces.side])
+            .filter_map(|appearance| match appearance {
+                Some(Appearance::Texture(name)) if !self.tiles.contains_key(name) => Some(name),
+                _ => None,
+            })
+            .collect();
+        missing.sort();
+        missing.dedup();
+        missing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use image::{Rgba, RgbaImage};
+    use atlas::TextureAtlas;
+    use block;
+    use block::Appearance;
+
+    fn tile(size: u32, colour: [u8; 4]) -> RgbaImage {
+        RgbaImage::from_pixel(size, size, Rgba(colour))
+    }
+
+    /// Colour of the atlas pixel under texture coordinates (u, v)
+    fn sample(atlas: &TextureAtlas, u: f32, v: f32) -> [u8; 4] {
+        sample_image(atlas.image(), u, v)
+    }
+
+    fn sample_image(image: &RgbaImage, u: f32, v: f32) -> [u8; 4] {
+        let x = (u * image.width() as f32) as u32;
+        let y = ((1.0 - v) * image.height() as f32) as u32;
+        image.get_pixel(x, y).0
+    }
+
+    /// The next mipmap level, averaging each 2x2 square of pixels
+    fn halve(image: &RgbaImage) -> RgbaImage {
+        RgbaImage::from_fn(image.width() / 2, image.height() / 2, |x, y| {
+            let pixels = [(0, 0), (1, 0), (0, 1), (1, 1)].iter().map(|&(dx, dy)| image.get_pixel(2 * x + dx, 2 * y + dy).0).collect::<Vec<_>>();
+            let mut average = [0; 4];
+            for channel in 0..4 {
+                average[channel] = (pixels.iter().map(|pixel| pixel[channel] as u32).sum::<u32>() / 4) as u8;
+            }
+            Rgba(average)
+        })
+    }
+
+    #[test]
+    fn atlas_packs_tiles() {
+        let atlas = TextureAtlas::new(vec![
+            ("red".to_owned(), tile(4, [255, 0, 0, 255])),
+            ("green".to_owned(), tile(4, [0, 255, 0, 255])),
+            ("blue".to_owned(), tile(4, [0, 0, 255, 255])),
+        ]).unwrap();
+        assert_eq!(atlas.image().dimensions(), (8, 8));
+        for &(name, colour) in [("red", [255, 0, 0, 255]), ("green", [0, 255, 0, 255]), ("blue", [0, 0, 255, 255])].iter() {
+            let uv = atlas.uv(name).unwrap();
+            assert_eq!(sample(&atlas, uv[0], uv[1]), colour);
+            assert_eq!(sample(&atlas, uv[2], uv[3]), colour);
+        }
+        assert_eq!(atlas.uv("purple"), None);
+
+        let (colour, uv) = atlas.face(None, block::STONE);
+        assert_eq!(colour, block::STONE.color);
+        assert_eq!(sample(&atlas, uv[0], uv[1]), [255, 255, 255, 255]);
+        assert_eq!(atlas.face(Some(Appearance::Texture("red")), block::STONE), ([1.0, 1.0, 1.0], atlas.uv("red").unwrap()));
+        assert_eq!(atlas.face(Some(Appearance::Texture("purple")), block::STONE).0, block::STONE.color);
+
+        assert!(TextureAtlas::new(vec![("a".to_owned(), tile(4, [0; 4])), ("b".to_owned(), tile(8, [0; 4]))]).is_err());
+    }
+
+    #[test]
+    fn atlas_mipmaps_keep_tiles_apart() {
+        let colours = [("red", [255, 0, 0, 255]), ("green", [0, 255, 0, 255]), ("blue", [0, 0, 255, 255])];
+        let atlas = TextureAtlas::new(colours.iter().map(|&(name, colour)| (name.to_owned(), tile(8, colour))).collect()).unwrap();
+        assert_eq!(atlas.mipmap_levels(), 3);
+        let mut level = atlas.image().clone();
+        for _ in 0..atlas.mipmap_levels() {
+            level = halve(&level);
+            for &(name, colour) in colours.iter() {
+                let uv = atlas.uv(name).unwrap();
+                assert_eq!(sample_image(&level, uv[0], uv[1]), colour);
+                assert_eq!(sample_image(&level, uv[2], uv[3]), colour);
+            }
+        }
+        // tiles which aren't a power of two across stop as soon as they'd be split unevenly
+        assert_eq!(TextureAtlas::new(vec![("a".to_owned(), tile(12, [0; 4]))]).unwrap().mipmap_levels(), 2);
+        assert_eq!(TextureAtlas::new(Vec::new()).unwrap().mipmap_levels(), 0);
+    }
+
+    #[test]
+    fn atlas_from_directory() {
+        let directory = env::temp_dir().join(format!("ave-atlas-{}", ::std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        tile(2, [10, 20, 30, 255]).save(directory.join("slate.png")).unwrap();
+        fs::write(directory.join("notes.txt"), "not a tile").unwrap();
+        let atlas = TextureAtlas::from_directory(&directory).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+        let uv = atlas.uv("slate").unwrap();
+        assert_eq!(sample(&atlas, uv[0], uv[1]), [10, 20, 30, 255]);
+        assert_eq!(atlas.tile_size(), 2);
+
+        let empty = TextureAtlas::from_directory(directory.join("missing")).unwrap();
+        assert_eq!(empty.image().dimensions(), (1, 1));
+    }
+}
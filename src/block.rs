@@ -3,6 +3,11 @@ use std::hash::{Hash, Hasher};
 use glium::vertex::VertexBuffer;
 use glium::backend::Facade;
 use color::Color;
+use atlas::{TextureAtlas, Uv};
+use block_state::{BlockState, Property, Axis};
+use shape::Shape;
+use light::{FaceLight, Light};
+use cgmath::InnerSpace;
 use space;
 use space::{Position, Direction};
 
@@ -70,16 +75,18 @@ pub fn cube_at(position: &Position) -> [Position; 8] {
     ]
 }
 
+/// Faces of a block hidden by its neighbours, which needn't be drawn
 pub struct Mask {
-    up: bool,
-    down: bool,
-    north: bool,
-    south: bool,
-    east: bool,
-    west: bool,
+    pub up: bool,
+    pub down: bool,
+    pub north: bool,
+    pub south: bool,
+    pub east: bool,
+    pub west: bool,
 }
 
 impl Mask {
+    #[allow(dead_code)]
     pub fn new() -> Mask {
         Mask {
             up: false,
@@ -92,55 +99,161 @@ impl Mask {
     }
 }
 
-/// Create a vertex buffer for a cube centred at (x, y, z)
-pub fn make_cube<F: ? Sized>(facade: &F, position: &Position, color: Color, mask: Mask) -> VertexBuffer<Vertex> where F: Facade {
-    let x = position[0];
-    let y = position[1];
-    let z = position[2];
-    let mut vertices = Vec::new();
-    if !mask.south {
-        vertices.push(Vertex::new([x + CUBE_VERTICES[0][0], y + CUBE_VERTICES[0][1], z + CUBE_VERTICES[0][2]], color, CUBE_NORMALS[0].into()));
-        vertices.push(Vertex::new([x + CUBE_VERTICES[1][0], y + CUBE_VERTICES[1][1], z + CUBE_VERTICES[1][2]], color, CUBE_NORMALS[0].into()));
-        vertices.push(Vertex::new([x + CUBE_VERTICES[2][0], y + CUBE_VERTICES[2][1], z + CUBE_VERTICES[2][2]], color, CUBE_NORMALS[0].into()));
-        vertices.push(Vertex::new([x + CUBE_VERTICES[3][0], y + CUBE_VERTICES[3][1], z + CUBE_VERTICES[3][2]], color, CUBE_NORMALS[0].into()));
-    }
-    if !mask.east {
-        vertices.push(Vertex::new([x + CUBE_VERTICES[4][0], y + CUBE_VERTICES[4][1], z + CUBE_VERTICES[4][2]], color, CUBE_NORMALS[1].into()));
-        vertices.push(Vertex::new([x + CUBE_VERTICES[5][0], y + CUBE_VERTICES[5][1], z + CUBE_VERTICES[5][2]], color, CUBE_NORMALS[1].into()));
-        vertices.push(Vertex::new([x + CUBE_VERTICES[6][0], y + CUBE_VERTICES[6][1], z + CUBE_VERTICES[6][2]], color, CUBE_NORMALS[1].into()));
-        vertices.push(Vertex::new([x + CUBE_VERTICES[7][0], y + CUBE_VERTICES[7][1], z + CUBE_VERTICES[7][2]], color, CUBE_NORMALS[1].into()));
-    }
-    if !mask.north {
-        vertices.push(Vertex::new([x + CUBE_VERTICES[8][0], y + CUBE_VERTICES[8][1], z + CUBE_VERTICES[8][2]], color, CUBE_NORMALS[2].into()));
-        vertices.push(Vertex::new([x + CUBE_VERTICES[9][0], y + CUBE_VERTICES[9][1], z + CUBE_VERTICES[9][2]], color, CUBE_NORMALS[2].into()));
-        vertices.push(Vertex::new([x + CUBE_VERTICES[10][0], y + CUBE_VERTICES[10][1], z + CUBE_VERTICES[10][2]], color, CUBE_NORMALS[2].into()));
-        vertices.push(Vertex::new([x + CUBE_VERTICES[11][0], y + CUBE_VERTICES[11][1], z + CUBE_VERTICES[11][2]], color, CUBE_NORMALS[2].into()));
+/// The axis each face of CUBE_VERTICES is perpendicular to, and whether it's on the far side
+/// of the block along that axis - e.g. the south face is at z = 1
+const FACE_PLANES: [(usize, bool); 6] = [(2, true), (0, true), (2, false), (0, false), (1, false), (1, true)];
+
+/// Where a point on a face falls within its texture tile, from 0 to 1 across and up - so a slab
+/// shows the bottom half of its side texture rather than all of it squashed
+fn tile_fraction(face: usize, point: [f32; 3]) -> [f32; 2] {
+    match face {
+        0 => [point[0], point[1]],
+        1 => [1.0 - point[2], point[1]],
+        2 => [1.0 - point[0], point[1]],
+        3 => [point[2], point[1]],
+        4 => [point[0], point[2]],
+        _ => [point[0], 1.0 - point[2]],
     }
-    if !mask.west {
-        vertices.push(Vertex::new([x + CUBE_VERTICES[12][0], y + CUBE_VERTICES[12][1], z + CUBE_VERTICES[12][2]], color, CUBE_NORMALS[3].into()));
-        vertices.push(Vertex::new([x + CUBE_VERTICES[13][0], y + CUBE_VERTICES[13][1], z + CUBE_VERTICES[13][2]], color, CUBE_NORMALS[3].into()));
-        vertices.push(Vertex::new([x + CUBE_VERTICES[14][0], y + CUBE_VERTICES[14][1], z + CUBE_VERTICES[14][2]], color, CUBE_NORMALS[3].into()));
-        vertices.push(Vertex::new([x + CUBE_VERTICES[15][0], y + CUBE_VERTICES[15][1], z + CUBE_VERTICES[15][2]], color, CUBE_NORMALS[3].into()));
+}
+
+/// Add a quad as two triangles, from corners ordered as in CUBE_VERTICES - `corners` are within
+/// the block, which is at `origin`
+fn push_quad(vertices: &mut Vec<Vertex>, origin: &Position, corners: [[f32; 3]; 4], fractions: [[f32; 2]; 4], normal: Direction, appearance: (Color, Uv), light: Light) {
+    let (color, uv) = appearance;
+    for &corner in [0, 1, 2, 2, 1, 3].iter() {
+        let point = corners[corner];
+        let fraction = fractions[corner];
+        let tex_coords = [uv[0] + fraction[0] * (uv[2] - uv[0]), uv[1] + fraction[1] * (uv[3] - uv[1])];
+        let position = [origin[0] + point[0] * BLOCK_SIZE, origin[1] + point[1] * BLOCK_SIZE, origin[2] + point[2] * BLOCK_SIZE];
+        vertices.push(Vertex::new(position, color, normal.into(), tex_coords, light.to_attribute()));
     }
-    if !mask.down {
-        vertices.push(Vertex::new([x + CUBE_VERTICES[16][0], y + CUBE_VERTICES[16][1], z + CUBE_VERTICES[16][2]], color, CUBE_NORMALS[4].into()));
-        vertices.push(Vertex::new([x + CUBE_VERTICES[17][0], y + CUBE_VERTICES[17][1], z + CUBE_VERTICES[17][2]], color, CUBE_NORMALS[4].into()));
-        vertices.push(Vertex::new([x + CUBE_VERTICES[18][0], y + CUBE_VERTICES[18][1], z + CUBE_VERTICES[18][2]], color, CUBE_NORMALS[4].into()));
-        vertices.push(Vertex::new([x + CUBE_VERTICES[19][0], y + CUBE_VERTICES[19][1], z + CUBE_VERTICES[19][2]], color, CUBE_NORMALS[4].into()));
+}
+
+/// Create a vertex buffer of triangles for a block at (x, y, z), shaped, coloured and textured as
+/// its block type says
+///
+/// Faces on the edge of the block are left out where `mask` says a neighbour hides them, and lit
+/// by the light in front of them - faces within the block by the light inside it. Blocks
+/// lying along the x or z axis (e.g. logs) are turned on their side, so their top and bottom face
+/// that way.
+pub fn make_block<F: ? Sized>(facade: &F, position: &Position, state: BlockState, atlas: &TextureAtlas, mask: Mask, light: FaceLight) -> VertexBuffer<Vertex> where F: Facade {
+    VertexBuffer::new(facade, block_vertices(position, state, atlas, mask, light).as_slice()).unwrap()
+}
+
+/// The triangles drawn by `make_block`, two to each face
+fn block_vertices(position: &Position, state: BlockState, atlas: &TextureAtlas, mask: Mask, light: FaceLight) -> Vec<Vertex> {
+    let block_type = state.block_type;
+    let side = atlas.face(block_type.faces.side, block_type);
+    let top = atlas.face(block_type.faces.top, block_type);
+    let bottom = atlas.face(block_type.faces.bottom, block_type);
+    // south, east, north, west, down and up - in the same order as CUBE_VERTICES
+    let appearances = match state.axis() {
+        Some(Axis::X) => [side, top, side, bottom, side, side],
+        Some(Axis::Z) => [top, side, bottom, side, side, side],
+        _ => [side, side, side, side, bottom, top],
+    };
+    let masked = [mask.south, mask.east, mask.north, mask.west, mask.down, mask.up];
+    let face_light = [light.south, light.east, light.north, light.west, light.down, light.up];
+    let mut vertices = Vec::new();
+    for cuboid in block_type.shape.boxes(state) {
+        for face in 0..6 {
+            let (axis, far) = FACE_PLANES[face];
+            let on_edge = if far { cuboid.max[axis] == 1.0 } else { cuboid.min[axis] == 0.0 };
+            if on_edge && masked[face] {
+                continue;
+            }
+            let mut corners = [[0.0; 3]; 4];
+            let mut fractions = [[0.0; 2]; 4];
+            for corner in 0..4 {
+                let vertex = CUBE_VERTICES[face * 4 + corner];
+                for k in 0..3 {
+                    let t = vertex[k] / BLOCK_SIZE;
+                    corners[corner][k] = cuboid.min[k] + t * (cuboid.max[k] - cuboid.min[k]);
+                }
+                fractions[corner] = tile_fraction(face, corners[corner]);
+            }
+            let light = if on_edge { face_light[face] } else { light.inside };
+            push_quad(&mut vertices, position, corners, fractions, CUBE_NORMALS[face], appearances[face], light);
+        }
     }
-    if !mask.up {
-        vertices.push(Vertex::new([x + CUBE_VERTICES[20][0], y + CUBE_VERTICES[20][1], z + CUBE_VERTICES[20][2]], color, CUBE_NORMALS[5].into()));
-        vertices.push(Vertex::new([x + CUBE_VERTICES[21][0], y + CUBE_VERTICES[21][1], z + CUBE_VERTICES[21][2]], color, CUBE_NORMALS[5].into()));
-        vertices.push(Vertex::new([x + CUBE_VERTICES[22][0], y + CUBE_VERTICES[22][1], z + CUBE_VERTICES[22][2]], color, CUBE_NORMALS[5].into()));
-        vertices.push(Vertex::new([x + CUBE_VERTICES[23][0], y + CUBE_VERTICES[23][1], z + CUBE_VERTICES[23][2]], color, CUBE_NORMALS[5].into()));
+    if block_type.shape == Shape::Cross {
+        // two planes corner to corner, each drawn from both sides
+        let fractions = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]];
+        let back = [[1.0, 0.0], [0.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        let planes = [
+            ([[0.0, 0.0, 0.0], [1.0, 0.0, 1.0], [0.0, 1.0, 0.0], [1.0, 1.0, 1.0]], Direction::new(-1.0, 0.0, 1.0)),
+            ([[1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [1.0, 1.0, 0.0], [0.0, 1.0, 1.0]], Direction::new(-1.0, 0.0, -1.0)),
+        ];
+        for &(corners, normal) in planes.iter() {
+            push_quad(&mut vertices, position, corners, fractions, normal.normalize(), side, light.inside);
+            let reversed = [corners[1], corners[0], corners[3], corners[2]];
+            push_quad(&mut vertices, position, reversed, back, -normal.normalize(), side, light.inside);
+        }
     }
-    VertexBuffer::new(facade, vertices.as_slice()).unwrap()
+    vertices
+}
+
+/// How a face of a block looks
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Appearance {
+    Color(Color),
+    /// A tile of the texture atlas, by name
+    Texture(&'static str),
+}
+
+/// How the top, bottom and sides of a block look - any left out are the block's colour
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Faces {
+    pub top: Option<Appearance>,
+    pub bottom: Option<Appearance>,
+    pub side: Option<Appearance>,
 }
 
+impl Faces {
+    /// Every face the block's colour
+    pub const PLAIN: Faces = Faces { top: None, bottom: None, side: None };
+}
+
+/// Brightest light a block can give off
+pub const MAX_EMISSION: u8 = 15;
+
 #[derive(Debug)]
 pub struct BlockType {
     pub name: &'static str,
+    /// Colour of the block as a whole, e.g. on maps
     pub color: Color,
+    pub faces: Faces,
+    /// Properties which can vary between blocks of this type, e.g. which way a log lies
+    pub properties: &'static [Property],
+    pub shape: Shape,
+    /// Opaque blocks hide whatever is behind them - others (e.g. water) are drawn after
+    /// everything else, blended over what's behind them
+    pub opaque: bool,
+    /// Solid blocks can't be moved through
+    pub solid: bool,
+    /// Light given off, from 0 (none) to `MAX_EMISSION`
+    pub emission: u8,
+    /// How long the block takes to break - stone is 1.5
+    #[allow(dead_code)]
+    pub hardness: f32,
+    /// Replaceable blocks (e.g. water) are overwritten by structures, rather than left alone
+    pub replaceable: bool,
+    /// Fluids (e.g. water) flow, and can be swum through
+    #[allow(dead_code)]
+    pub fluid: bool,
+}
+
+impl BlockType {
+    /// Whether this block hides the face of a `neighbour` touching it - only whole blocks can,
+    /// and see-through ones only hide others of the same type, e.g. water next to water
+    pub fn hides(&self, neighbour: Option<&BlockType>) -> bool {
+        self.shape.is_full() && (self.opaque || Some(self) == neighbour)
+    }
+
+    /// Whether light can't pass through this block
+    pub fn blocks_light(&self) -> bool {
+        self.opaque && self.shape.is_full()
+    }
 }
 
 impl Hash for BlockType {
@@ -160,24 +273,208 @@ impl Eq for BlockType {}
 pub static GRASS: &BlockType = &BlockType {
     name: "grass",
     color: [0.196, 0.804, 0.196],
+    faces: Faces {
+        top: None,
+        bottom: Some(Appearance::Color([0.545, 0.271, 0.075])),
+        side: Some(Appearance::Color([0.545, 0.271, 0.075])),
+    },
+    properties: &[],
+    shape: Shape::Cube,
+    opaque: true,
+    solid: true,
+    emission: 0,
+    hardness: 0.6,
+    replaceable: false,
+    fluid: false,
 };
 
 pub static DIRT: &BlockType = &BlockType {
     name: "dirt",
     color: [0.545, 0.271, 0.075],
+    faces: Faces::PLAIN,
+    properties: &[],
+    shape: Shape::Cube,
+    opaque: true,
+    solid: true,
+    emission: 0,
+    hardness: 0.5,
+    replaceable: false,
+    fluid: false,
 };
 
 pub static STONE: &BlockType = &BlockType {
     name: "stone",
     color: [0.827, 0.827, 0.827],
+    faces: Faces::PLAIN,
+    properties: &[],
+    shape: Shape::Cube,
+    opaque: true,
+    solid: true,
+    emission: 0,
+    hardness: 1.5,
+    replaceable: false,
+    fluid: false,
 };
-// add water later aka TODO lol
-/*pub static WATER: &BlockType = &BlockType {
+pub static WATER: &BlockType = &BlockType {
     name: "water",
     color: [0.498, 1.000, 0.831],
-}; */
+    faces: Faces::PLAIN,
+    properties: &[],
+    shape: Shape::Cube,
+    opaque: false,
+    solid: false,
+    emission: 0,
+    hardness: 100.0,
+    replaceable: true,
+    fluid: true,
+};
 
 pub static SAND: &BlockType = &BlockType {
     name: "sand",
     color: [0.941, 0.902, 0.549],
+    faces: Faces::PLAIN,
+    properties: &[],
+    shape: Shape::Cube,
+    opaque: true,
+    solid: true,
+    emission: 0,
+    hardness: 0.5,
+    replaceable: false,
+    fluid: false,
+};
+
+pub static SNOW: &BlockType = &BlockType {
+    name: "snow",
+    color: [0.980, 0.980, 0.980],
+    faces: Faces::PLAIN,
+    properties: &[],
+    shape: Shape::Cube,
+    opaque: true,
+    solid: true,
+    emission: 0,
+    hardness: 0.2,
+    replaceable: false,
+    fluid: false,
+};
+
+pub static CACTUS: &BlockType = &BlockType {
+    name: "cactus",
+    color: [0.133, 0.545, 0.133],
+    faces: Faces::PLAIN,
+    properties: &[],
+    shape: Shape::Cube,
+    opaque: true,
+    solid: true,
+    emission: 0,
+    hardness: 0.4,
+    replaceable: false,
+    fluid: false,
+};
+
+pub static COAL_ORE: &BlockType = &BlockType {
+    name: "coal_ore",
+    color: [0.212, 0.212, 0.212],
+    faces: Faces::PLAIN,
+    properties: &[],
+    shape: Shape::Cube,
+    opaque: true,
+    solid: true,
+    emission: 0,
+    hardness: 3.0,
+    replaceable: false,
+    fluid: false,
+};
+
+pub static IRON_ORE: &BlockType = &BlockType {
+    name: "iron_ore",
+    color: [0.824, 0.706, 0.549],
+    faces: Faces::PLAIN,
+    properties: &[],
+    shape: Shape::Cube,
+    opaque: true,
+    solid: true,
+    emission: 0,
+    hardness: 3.0,
+    replaceable: false,
+    fluid: false,
+};
+
+pub static GOLD_ORE: &BlockType = &BlockType {
+    name: "gold_ore",
+    color: [1.000, 0.843, 0.000],
+    faces: Faces::PLAIN,
+    properties: &[],
+    shape: Shape::Cube,
+    opaque: true,
+    solid: true,
+    emission: 0,
+    hardness: 3.0,
+    replaceable: false,
+    fluid: false,
+};
+
+pub static WOOD: &BlockType = &BlockType {
+    name: "wood",
+    color: [0.400, 0.263, 0.129],
+    faces: Faces {
+        top: Some(Appearance::Color([0.690, 0.549, 0.361])),
+        bottom: Some(Appearance::Color([0.690, 0.549, 0.361])),
+        side: None,
+    },
+    properties: &[Property::Axis],
+    shape: Shape::Cube,
+    opaque: true,
+    solid: true,
+    emission: 0,
+    hardness: 2.0,
+    replaceable: false,
+    fluid: false,
 };
+
+pub static LEAVES: &BlockType = &BlockType {
+    name: "leaves",
+    color: [0.000, 0.392, 0.000],
+    faces: Faces::PLAIN,
+    properties: &[],
+    shape: Shape::Cube,
+    opaque: true,
+    solid: true,
+    emission: 0,
+    hardness: 0.2,
+    replaceable: false,
+    fluid: false,
+};
+
+/// All built in block types
+pub static BLOCK_TYPES: [&BlockType; 12] = [GRASS, DIRT, STONE, WATER, SAND, SNOW, CACTUS, COAL_ORE, IRON_ORE, GOLD_ORE, WOOD, LEAVES];
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3};
+    use atlas::TextureAtlas;
+    use block;
+    use block::{block_vertices, BlockType, Mask};
+    use block_state::BlockState;
+    use light::{FaceLight, Light};
+    use shape::Shape;
+    use space::Position;
+
+    static FLOWER: &BlockType = &BlockType { shape: Shape::Cross, ..*block::LEAVES };
+
+    #[test]
+    fn block_normals_face_the_way_triangles_wind() {
+        let atlas = TextureAtlas::new(Vec::new()).unwrap();
+        let light = Light::OPEN_SKY;
+        for &block_type in [block::STONE, FLOWER].iter() {
+            let face_light = FaceLight { up: light, down: light, north: light, south: light, east: light, west: light, inside: light };
+            let vertices = block_vertices(&Position::new(0.0, 0.0, 0.0), BlockState::new(block_type), &atlas, Mask::new(), face_light);
+            assert!(!vertices.is_empty());
+            for triangle in vertices.chunks(3) {
+                let corner = |i: usize| Vector3::from(triangle[i].position);
+                let wound = (corner(1) - corner(0)).cross(corner(2) - corner(0)).normalize();
+                let normal = Vector3::from(triangle[0].normal);
+                assert!((wound - normal).magnitude() < 1e-5, "{} triangle wound {:?} has normal {:?}", block_type.name, wound, normal);
+            }
+        }
+    }
+}
@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use toml;
+use block::{Appearance, BlockType, Faces, BLOCK_TYPES, MAX_EMISSION};
+use block_state::Property;
+use color::Color;
+use shape::{Cuboid, Shape};
+
+/// Where extra block types are read from, relative to the working directory
+pub const DEFAULT_PATH: &str = "blocks.toml";
+
+/// Compact numeric ID of a block type, assigned in registration order - the built in blocks
+/// always come first, so their IDs never change
+pub type BlockId = u16;
+
+/// A block type as written in a blocks file, e.g.
+///
+/// ```toml
+/// [[blocks]]
+/// name = "marble"
+/// color = [0.9, 0.9, 0.95]
+/// top = "marble_top"
+/// properties = ["axis"]
+/// hardness = 2.0
+///
+/// [[blocks]]
+/// name = "marble_slab"
+/// color = [0.9, 0.9, 0.95]
+/// shape = "slab"
+/// properties = ["half"]
+/// ```
+///
+/// Any missing property is that of an ordinary solid block, like stone.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct BlockDefinition {
+    name: String,
+    color: Color,
+    top: Option<FaceDefinition>,
+    bottom: Option<FaceDefinition>,
+    side: Option<FaceDefinition>,
+    #[serde(default)]
+    properties: Vec<String>,
+    shape: Option<ShapeDefinition>,
+    #[serde(default = "yes")]
+    opaque: bool,
+    #[serde(default = "yes")]
+    solid: bool,
+    #[serde(default)]
+    emission: u8,
+    #[serde(default = "default_hardness")]
+    hardness: f32,
+    #[serde(default)]
+    replaceable: bool,
+    #[serde(default)]
+    fluid: bool,
+}
+
+/// A face is either a colour or the name of a texture tile
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+enum FaceDefinition {
+    Color(Color),
+    Texture(String),
+}
+
+impl FaceDefinition {
+    fn into_appearance(self) -> Appearance {
+        match self {
+            FaceDefinition::Color(color) => Appearance::Color(color),
+            FaceDefinition::Texture(name) => Appearance::Texture(Box::leak(name.into_boxed_str())),
+        }
+    }
+}
+
+/// A shape is either a name, e.g. `stairs`, or a list of boxes as `[x0, y0, z0, x1, y1, z1]`,
+/// measured within the block from 0 to 1
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+enum ShapeDefinition {
+    Name(String),
+    Boxes(Vec<[f32; 6]>),
+}
+
+impl ShapeDefinition {
+    /// What's wrong with the shape, if anything
+    fn validate(&self) -> Option<String> {
+        match *self {
+            ShapeDefinition::Name(ref name) if Shape::from_name(name).is_none() => Some(format!("unknown shape {:?}", name)),
+            ShapeDefinition::Name(_) => None,
+            ShapeDefinition::Boxes(ref boxes) if boxes.is_empty() => Some("shape must have at least one box".to_owned()),
+            ShapeDefinition::Boxes(ref boxes) => {
+                let valid = boxes.iter().all(|b| (0..3).all(|axis| 0.0 <= b[axis] && b[axis] < b[axis + 3] && b[axis + 3] <= 1.0));
+                if valid { None } else { Some("shape boxes must go from low to high corner, within 0 to 1".to_owned()) }
+            },
+        }
+    }
+
+    fn into_shape(self) -> Shape {
+        match self {
+            ShapeDefinition::Name(name) => Shape::from_name(&name).unwrap_or(Shape::Cube),
+            ShapeDefinition::Boxes(boxes) => {
+                let cuboids: Vec<Cuboid> = boxes.iter().map(|b| Cuboid::new([b[0], b[1], b[2]], [b[3], b[4], b[5]])).collect();
+                Shape::Boxes(Box::leak(cuboids.into_boxed_slice()))
+            },
+        }
+    }
+}
+
+fn is_color(color: &Color) -> bool {
+    color.iter().all(|c| (0.0..=1.0).contains(c))
+}
+
+fn yes() -> bool {
+    true
+}
+
+fn default_hardness() -> f32 {
+    1.5
+}
+
+#[derive(Debug, Deserialize)]
+struct BlocksFile {
+    #[serde(default)]
+    blocks: Vec<BlockDefinition>,
+}
+
+/// Every block type the game knows about, looked up by name or numeric ID
+///
+/// Block types are `&'static` like the built in ones, so the rest of the game doesn't care where
+/// a block came from - those loaded from a file live until the game exits.
+pub struct BlockRegistry {
+    /// Indexed by ID
+    blocks: Vec<&'static BlockType>,
+    ids: HashMap<&'static str, BlockId>,
+}
+
+#[allow(dead_code)]
+impl BlockRegistry {
+    /// A registry with no blocks at all, not even the built in ones
+    pub fn empty() -> BlockRegistry {
+        BlockRegistry { blocks: Vec::new(), ids: HashMap::new() }
+    }
+
+    /// Add a block type, giving it the next free ID - its name mustn't be taken already
+    pub fn register(&mut self, block_type: &'static BlockType) -> Result<BlockId, String> {
+        if self.ids.contains_key(block_type.name) {
+            return Err(format!("a block named {:?} is already registered", block_type.name));
+        }
+        if self.blocks.len() > BlockId::MAX as usize {
+            return Err(format!("too many block types - at most {} are allowed", BlockId::MAX as usize + 1));
+        }
+        let id = self.blocks.len() as BlockId;
+        self.blocks.push(block_type);
+        self.ids.insert(block_type.name, id);
+        Ok(id)
+    }
+
+    /// The built in blocks, plus any in the file at `path` - a missing file just adds nothing
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<BlockRegistry, String> {
+        let path = path.as_ref();
+        let mut registry = BlockRegistry::default();
+        match fs::read_to_string(path) {
+            Ok(contents) => registry.parse(&contents).map_err(|e| format!("invalid blocks in {}: {}", path.display(), e))?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => return Err(format!("couldn't read {}: {}", path.display(), e)),
+        }
+        Ok(registry)
+    }
+
+    /// Register every block defined in TOML, reporting all problems at once rather than just the
+    /// first - nothing is registered unless every block is valid
+    pub fn parse(&mut self, contents: &str) -> Result<(), String> {
+        let file: BlocksFile = toml::from_str(contents).map_err(|e| e.to_string())?;
+        let mut errors = Vec::new();
+        for (i, definition) in file.blocks.iter().enumerate() {
+            let name = &definition.name;
+            if name.is_empty() {
+                errors.push(format!("blocks[{}]: name must not be empty", i));
+            } else if self.ids.contains_key(name.as_str()) || file.blocks[..i].iter().any(|other| &other.name == name) {
+                errors.push(format!("blocks[{}]: a block named {:?} already exists", i, name));
+            }
+            let faces = [&definition.top, &definition.bottom, &definition.side];
+            let face_colors = faces.iter().filter_map(|face| match **face {
+                Some(FaceDefinition::Color(ref color)) => Some(color),
+                _ => None,
+            });
+            if !Some(&definition.color).into_iter().chain(face_colors).all(is_color) {
+                errors.push(format!("blocks[{}]: color components must be between 0 and 1", i));
+            }
+            for (j, property) in definition.properties.iter().enumerate() {
+                if Property::from_name(property).is_none() {
+                    errors.push(format!("blocks[{}]: unknown property {:?}", i, property));
+                } else if definition.properties[..j].contains(property) {
+                    errors.push(format!("blocks[{}]: property {:?} is listed twice", i, property));
+                }
+            }
+            if let Some(error) = definition.shape.as_ref().and_then(ShapeDefinition::validate) {
+                errors.push(format!("blocks[{}]: {}", i, error));
+            }
+            if definition.emission > MAX_EMISSION {
+                errors.push(format!("blocks[{}]: emission must be at most {}", i, MAX_EMISSION));
+            }
+            if definition.hardness < 0.0 || definition.hardness.is_nan() {
+                errors.push(format!("blocks[{}]: hardness must not be negative", i));
+            }
+        }
+        if self.blocks.len() + file.blocks.len() > BlockId::MAX as usize + 1 {
+            errors.push(format!("too many block types - at most {} are allowed", BlockId::MAX as usize + 1));
+        }
+        if !errors.is_empty() {
+            return Err(errors.join("\n"));
+        }
+        for definition in file.blocks {
+            let block_type = BlockType {
+                name: Box::leak(definition.name.into_boxed_str()),
+                color: definition.color,
+                faces: Faces {
+                    top: definition.top.map(FaceDefinition::into_appearance),
+                    bottom: definition.bottom.map(FaceDefinition::into_appearance),
+                    side: definition.side.map(FaceDefinition::into_appearance),
+                },
+                properties: Box::leak(definition.properties.iter().filter_map(|name| Property::from_name(name)).collect::<Vec<_>>().into_boxed_slice()),
+                shape: definition.shape.map_or(Shape::Cube, ShapeDefinition::into_shape),
+                opaque: definition.opaque,
+                solid: definition.solid,
+                emission: definition.emission,
+                hardness: definition.hardness,
+                replaceable: definition.replaceable,
+                fluid: definition.fluid,
+            };
+            self.register(Box::leak(Box::new(block_type)))?;
+        }
+        Ok(())
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<&'static BlockType> {
+        self.ids.get(name).map(|&id| self.blocks[id as usize])
+    }
+
+    pub fn by_id(&self, id: BlockId) -> Option<&'static BlockType> {
+        self.blocks.get(id as usize).cloned()
+    }
+
+    pub fn id(&self, block_type: &BlockType) -> Option<BlockId> {
+        self.ids.get(block_type.name).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Every block type, in ID order
+    pub fn iter(&self) -> impl Iterator<Item = (BlockId, &'static BlockType)> + '_ {
+        self.blocks.iter().enumerate().map(|(id, &block_type)| (id as BlockId, block_type))
+    }
+}
+
+/// The built in blocks, in the order of `BLOCK_TYPES`
+impl Default for BlockRegistry {
+    fn default() -> BlockRegistry {
+        let mut registry = BlockRegistry::empty();
+        for &block_type in BLOCK_TYPES.iter() {
+            registry.register(block_type).unwrap();
+        }
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use block;
+    use block::{Appearance, Faces};
+    use block_registry::BlockRegistry;
+    use block_state::Property;
+    use shape::{Cuboid, Shape};
+
+    #[test]
+    fn block_registry_builtins() {
+        let registry = BlockRegistry::default();
+        assert_eq!(registry.len(), block::BLOCK_TYPES.len());
+        assert_eq!(registry.id(block::GRASS), Some(0));
+        assert_eq!(registry.by_id(2), Some(block::STONE));
+        assert_eq!(registry.by_name("leaves"), Some(block::LEAVES));
+        assert_eq!(registry.by_name("marble"), None);
+        assert_eq!(registry.by_id(registry.len() as u16), None);
+    }
+
+    #[test]
+    fn block_registry_parse() {
+        let mut registry = BlockRegistry::default();
+        registry.parse("[[blocks]]\nname = \"marble\"\ncolor = [0.9, 0.9, 0.95]\n\n[[blocks]]\nname = \"ice\"\ncolor = [0.7, 0.8, 1.0]\nopaque = false\nemission = 4\nhardness = 0.5\n").unwrap();
+        let marble = registry.by_name("marble").unwrap();
+        assert_eq!(marble.color, [0.9, 0.9, 0.95]);
+        assert!(marble.opaque && marble.solid && !marble.fluid && !marble.replaceable);
+        assert_eq!((marble.emission, marble.hardness), (0, 1.5));
+        assert_eq!(marble.faces, Faces::PLAIN);
+        let ice = registry.by_name("ice").unwrap();
+        assert!(!ice.opaque && ice.solid);
+        assert_eq!((ice.emission, ice.hardness), (4, 0.5));
+        let id = registry.id(marble).unwrap();
+        assert_eq!(id as usize, block::BLOCK_TYPES.len());
+        assert_eq!(registry.by_id(id), Some(marble));
+        assert_eq!(registry.iter().last().map(|(_, block_type)| block_type.name), Some("ice"));
+    }
+
+    #[test]
+    fn block_registry_faces() {
+        let mut registry = BlockRegistry::default();
+        registry.parse("[[blocks]]\nname = \"log\"\ncolor = [0.4, 0.3, 0.1]\ntop = \"log_top\"\nbottom = [0.7, 0.5, 0.4]\nproperties = [\"axis\"]\n").unwrap();
+        let log = registry.by_name("log").unwrap();
+        assert_eq!(log.properties, &[Property::Axis]);
+        let faces = log.faces;
+        assert_eq!(faces.top, Some(Appearance::Texture("log_top")));
+        assert_eq!(faces.bottom, Some(Appearance::Color([0.7, 0.5, 0.4])));
+        assert_eq!(faces.side, None);
+    }
+
+    #[test]
+    fn block_registry_shapes() {
+        let mut registry = BlockRegistry::default();
+        registry.parse("[[blocks]]\nname = \"slab\"\ncolor = [0.5, 0.5, 0.5]\nshape = \"slab\"\n\n[[blocks]]\nname = \"table\"\ncolor = [0.5, 0.5, 0.5]\nshape = [[0.0, 0.8, 0.0, 1.0, 1.0, 1.0], [0.4, 0.0, 0.4, 0.6, 0.8, 0.6]]\n").unwrap();
+        assert_eq!(registry.by_name("slab").unwrap().shape, Shape::Slab);
+        let table = [Cuboid::new([0.0, 0.8, 0.0], [1.0, 1.0, 1.0]), Cuboid::new([0.4, 0.0, 0.4], [0.6, 0.8, 0.6])];
+        match registry.by_name("table").unwrap().shape {
+            Shape::Boxes(boxes) => assert_eq!(boxes, &table),
+            shape => panic!("expected boxes, got {:?}", shape),
+        }
+        assert_eq!(registry.by_name("stone").unwrap().shape, Shape::Cube);
+
+        assert!(registry.parse("[[blocks]]\nname = \"blob\"\ncolor = [0.5, 0.5, 0.5]\nshape = \"sphere\"\n").is_err());
+        assert!(registry.parse("[[blocks]]\nname = \"blob\"\ncolor = [0.5, 0.5, 0.5]\nshape = []\n").is_err());
+        assert!(registry.parse("[[blocks]]\nname = \"blob\"\ncolor = [0.5, 0.5, 0.5]\nshape = [[0.0, 0.0, 0.0, 1.0, 2.0, 1.0]]\n").is_err());
+        assert!(registry.parse("[[blocks]]\nname = \"blob\"\ncolor = [0.5, 0.5, 0.5]\nshape = [[0.5, 0.0, 0.0, 0.5, 1.0, 1.0]]\n").is_err());
+    }
+
+    #[test]
+    fn block_registry_validation() {
+        let mut registry = BlockRegistry::default();
+        assert!(registry.parse("[[blocks]]\nname = \"stone\"\ncolor = [0.5, 0.5, 0.5]\n").is_err());
+        assert!(registry.parse("[[blocks]]\nname = \"a\"\ncolor = [0.5, 0.5, 0.5]\n[[blocks]]\nname = \"a\"\ncolor = [0.5, 0.5, 0.5]\n").is_err());
+        assert!(registry.parse("[[blocks]]\nname = \"\"\ncolor = [0.5, 0.5, 0.5]\n").is_err());
+        assert!(registry.parse("[[blocks]]\nname = \"glow\"\ncolor = [2.0, 0.5, 0.5]\n").is_err());
+        assert!(registry.parse("[[blocks]]\nname = \"glow\"\n").is_err());
+        assert!(registry.parse("[[blocks]]\nname = \"glow\"\ncolor = [0.5, 0.5, 0.5]\nproperties = [\"colour\"]\n").is_err());
+        assert!(registry.parse("[[blocks]]\nname = \"glow\"\ncolor = [0.5, 0.5, 0.5]\nproperties = [\"open\", \"open\"]\n").is_err());
+        assert!(registry.parse("[[blocks]]\nname = \"glow\"\ncolor = [0.5, 0.5, 0.5]\ntop = [0.5, 1.5, 0.5]\n").is_err());
+        assert!(registry.parse("[[blocks]]\nname = \"glow\"\ncolor = [0.5, 0.5, 0.5]\nemission = 16\n").is_err());
+        assert!(registry.parse("[[blocks]]\nname = \"glow\"\ncolor = [0.5, 0.5, 0.5]\nhardness = -1.0\n").is_err());
+        // a failed parse registers nothing
+        assert_eq!(registry.len(), block::BLOCK_TYPES.len());
+        assert!(registry.register(block::STONE).is_err());
+    }
+}
@@ -0,0 +1,332 @@
+//! Variants of a block, e.g. which way a log lies or whether a door is open
+use std::fmt;
+use block::BlockType;
+use block_registry::BlockRegistry;
+
+/// Something which can vary between blocks of the same type
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Property {
+    Facing,
+    Axis,
+    Half,
+    Open,
+}
+
+/// Which way a block faces, e.g. stairs or a door
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Facing {
+    North,
+    East,
+    South,
+    West,
+    Up,
+    Down,
+}
+
+/// Which axis a block lies along, e.g. a log
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+/// Which half of a space a block fills, e.g. a slab or the top of a door
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Half {
+    Bottom,
+    Top,
+}
+
+/// The value of a single property
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Value {
+    Facing(Facing),
+    Axis(Axis),
+    Half(Half),
+    Open(bool),
+}
+
+const FACINGS: [Facing; 6] = [Facing::North, Facing::East, Facing::South, Facing::West, Facing::Up, Facing::Down];
+const AXES: [Axis; 3] = [Axis::X, Axis::Y, Axis::Z];
+
+// where each property is packed, as (shift, mask)
+const FACING_BITS: (u8, u8) = (0, 0b111);
+const AXIS_BITS: (u8, u8) = (3, 0b11);
+const HALF_BITS: (u8, u8) = (5, 0b1);
+const OPEN_BITS: (u8, u8) = (6, 0b1);
+
+impl Property {
+    pub fn name(self) -> &'static str {
+        match self {
+            Property::Facing => "facing",
+            Property::Axis => "axis",
+            Property::Half => "half",
+            Property::Open => "open",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Property> {
+        [Property::Facing, Property::Axis, Property::Half, Property::Open].iter().cloned().find(|property| property.name() == name)
+    }
+
+    /// Value of a block which hasn't been given one
+    pub fn default_value(self) -> Value {
+        match self {
+            Property::Facing => Value::Facing(Facing::North),
+            Property::Axis => Value::Axis(Axis::Y),
+            Property::Half => Value::Half(Half::Bottom),
+            Property::Open => Value::Open(false),
+        }
+    }
+
+    /// Parse a value of this property, e.g. `north` for `facing`
+    pub fn parse(self, value: &str) -> Option<Value> {
+        match self {
+            Property::Facing => FACINGS.iter().cloned().find(|&facing| Value::Facing(facing).name() == value).map(Value::Facing),
+            Property::Axis => AXES.iter().cloned().find(|&axis| Value::Axis(axis).name() == value).map(Value::Axis),
+            Property::Half => [Half::Bottom, Half::Top].iter().cloned().find(|&half| Value::Half(half).name() == value).map(Value::Half),
+            Property::Open => value.parse().ok().map(Value::Open),
+        }
+    }
+
+    fn bits(self) -> (u8, u8) {
+        match self {
+            Property::Facing => FACING_BITS,
+            Property::Axis => AXIS_BITS,
+            Property::Half => HALF_BITS,
+            Property::Open => OPEN_BITS,
+        }
+    }
+}
+
+impl Value {
+    pub fn property(self) -> Property {
+        match self {
+            Value::Facing(_) => Property::Facing,
+            Value::Axis(_) => Property::Axis,
+            Value::Half(_) => Property::Half,
+            Value::Open(_) => Property::Open,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Value::Facing(Facing::North) => "north",
+            Value::Facing(Facing::East) => "east",
+            Value::Facing(Facing::South) => "south",
+            Value::Facing(Facing::West) => "west",
+            Value::Facing(Facing::Up) => "up",
+            Value::Facing(Facing::Down) => "down",
+            Value::Axis(Axis::X) => "x",
+            Value::Axis(Axis::Y) => "y",
+            Value::Axis(Axis::Z) => "z",
+            Value::Half(Half::Bottom) => "bottom",
+            Value::Half(Half::Top) => "top",
+            Value::Open(false) => "false",
+            Value::Open(true) => "true",
+        }
+    }
+
+    fn to_bits(self) -> u8 {
+        match self {
+            Value::Facing(facing) => FACINGS.iter().position(|&f| f == facing).unwrap() as u8,
+            Value::Axis(axis) => AXES.iter().position(|&a| a == axis).unwrap() as u8,
+            Value::Half(half) => (half == Half::Top) as u8,
+            Value::Open(open) => open as u8,
+        }
+    }
+
+    fn from_bits(property: Property, bits: u8) -> Value {
+        match property {
+            Property::Facing => Value::Facing(FACINGS[bits as usize % FACINGS.len()]),
+            Property::Axis => Value::Axis(AXES[bits as usize % AXES.len()]),
+            Property::Half => Value::Half(if bits == 1 { Half::Top } else { Half::Bottom }),
+            Property::Open => Value::Open(bits == 1),
+        }
+    }
+}
+
+/// A block type together with the values of its properties, packed into a single byte
+///
+/// Which properties a block has is up to its type - setting any other is an error.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub struct BlockState {
+    pub block_type: &'static BlockType,
+    bits: u8,
+}
+
+impl BlockState {
+    /// A block with every property at its default value
+    pub fn new(block_type: &'static BlockType) -> BlockState {
+        let bits = block_type.properties.iter().fold(0, |bits, property| pack(bits, property.default_value()));
+        BlockState { block_type, bits }
+    }
+
+    /// Unpack a state stored with `bits`
+    pub fn from_bits(block_type: &'static BlockType, bits: u8) -> BlockState {
+        BlockState { block_type, bits }
+    }
+
+    /// Every property packed into a byte, for storing compactly
+    pub fn bits(&self) -> u8 {
+        self.bits
+    }
+
+    /// Whether every property is at its default value, so only the block type needs storing
+    pub fn is_default(&self) -> bool {
+        *self == BlockState::new(self.block_type)
+    }
+
+    /// The value of `property`, if this block has it
+    pub fn get(&self, property: Property) -> Option<Value> {
+        if !self.block_type.properties.contains(&property) {
+            return None;
+        }
+        let (shift, mask) = property.bits();
+        Some(Value::from_bits(property, (self.bits >> shift) & mask))
+    }
+
+    /// This block with a property changed
+    pub fn with(self, value: Value) -> Result<BlockState, String> {
+        if !self.block_type.properties.contains(&value.property()) {
+            return Err(format!("{} blocks have no {} property", self.block_type.name, value.property().name()));
+        }
+        Ok(BlockState { block_type: self.block_type, bits: pack(self.bits, value) })
+    }
+
+    #[allow(dead_code)]
+    pub fn facing(&self) -> Option<Facing> {
+        match self.get(Property::Facing) {
+            Some(Value::Facing(facing)) => Some(facing),
+            _ => None,
+        }
+    }
+
+    pub fn axis(&self) -> Option<Axis> {
+        match self.get(Property::Axis) {
+            Some(Value::Axis(axis)) => Some(axis),
+            _ => None,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn half(&self) -> Option<Half> {
+        match self.get(Property::Half) {
+            Some(Value::Half(half)) => Some(half),
+            _ => None,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn open(&self) -> Option<bool> {
+        match self.get(Property::Open) {
+            Some(Value::Open(open)) => Some(open),
+            _ => None,
+        }
+    }
+
+    /// Parse a state written as by `Display`, e.g. `wood[axis=x]` - properties left out keep
+    /// their default values, and the brackets can be left out altogether
+    #[allow(dead_code)]
+    pub fn parse(state: &str, blocks: &BlockRegistry) -> Result<BlockState, String> {
+        let state = state.trim();
+        let (name, properties) = match state.find('[') {
+            Some(start) => {
+                if !state.ends_with(']') {
+                    return Err(format!("missing ] in block state {:?}", state));
+                }
+                (&state[..start], Some(&state[start + 1..state.len() - 1]))
+            },
+            None => (state, None),
+        };
+        let block_type = blocks.by_name(name.trim()).ok_or_else(|| format!("unknown block {:?}", name))?;
+        let mut block_state = BlockState::new(block_type);
+        for pair in properties.into_iter().flat_map(|properties| properties.split(',')).filter(|pair| !pair.trim().is_empty()) {
+            let mut parts = pair.splitn(2, '=');
+            let (property, value) = (parts.next().unwrap().trim(), parts.next().map(str::trim));
+            let property = Property::from_name(property).ok_or_else(|| format!("unknown property {:?}", property))?;
+            let value = value.and_then(|value| property.parse(value))
+                .ok_or_else(|| format!("invalid value for {} in {:?}", property.name(), pair.trim()))?;
+            block_state = block_state.with(value)?;
+        }
+        Ok(block_state)
+    }
+}
+
+fn pack(bits: u8, value: Value) -> u8 {
+    let (shift, mask) = value.property().bits();
+    (bits & !(mask << shift)) | (value.to_bits() << shift)
+}
+
+/// Writes the block's name, then every property it has, e.g. `wood[axis=y]`
+impl fmt::Display for BlockState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.block_type.name)?;
+        if self.block_type.properties.is_empty() {
+            return Ok(());
+        }
+        let values: Vec<String> = self.block_type.properties.iter()
+            .filter_map(|&property| self.get(property))
+            .map(|value| format!("{}={}", value.property().name(), value.name()))
+            .collect();
+        write!(f, "[{}]", values.join(","))
+    }
+}
+
+impl fmt::Debug for BlockState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "BlockState({})", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use block;
+    use block::BlockType;
+    use block_registry::BlockRegistry;
+    use block_state::{BlockState, Property, Value, Facing, Axis, Half};
+
+    static DOOR: &BlockType = &BlockType {
+        properties: &[Property::Facing, Property::Half, Property::Open],
+        ..*block::WOOD
+    };
+
+    #[test]
+    fn block_state_properties() {
+        let log = BlockState::new(block::WOOD);
+        assert_eq!(log.axis(), Some(Axis::Y));
+        assert!(log.is_default());
+        let log = log.with(Value::Axis(Axis::X)).unwrap();
+        assert_eq!(log.axis(), Some(Axis::X));
+        assert!(!log.is_default());
+        assert_eq!(log.get(Property::Facing), None);
+        assert!(log.with(Value::Open(true)).is_err());
+        assert!(BlockState::new(block::STONE).with(Value::Axis(Axis::X)).is_err());
+
+        let door = BlockState::new(DOOR)
+            .with(Value::Facing(Facing::West)).unwrap()
+            .with(Value::Half(Half::Top)).unwrap()
+            .with(Value::Open(true)).unwrap();
+        assert_eq!((door.facing(), door.half(), door.open()), (Some(Facing::West), Some(Half::Top), Some(true)));
+        let closed = door.with(Value::Open(false)).unwrap();
+        assert_eq!((closed.facing(), closed.half(), closed.open()), (Some(Facing::West), Some(Half::Top), Some(false)));
+        assert_eq!(BlockState::from_bits(DOOR, door.bits()), door);
+    }
+
+    #[test]
+    fn block_state_names() {
+        let registry = BlockRegistry::default();
+        let log = BlockState::new(block::WOOD).with(Value::Axis(Axis::Z)).unwrap();
+        assert_eq!(log.to_string(), "wood[axis=z]");
+        assert_eq!(BlockState::parse("wood[axis=z]", &registry), Ok(log));
+        assert_eq!(BlockState::parse("wood", &registry), Ok(BlockState::new(block::WOOD)));
+        assert_eq!(BlockState::new(block::STONE).to_string(), "stone");
+        assert_eq!(BlockState::parse("stone[]", &registry), Ok(BlockState::new(block::STONE)));
+        assert!(BlockState::parse("wood[axis=w]", &registry).is_err());
+        assert!(BlockState::parse("wood[colour=red]", &registry).is_err());
+        assert!(BlockState::parse("wood[open=true]", &registry).is_err());
+        assert!(BlockState::parse("wood[axis=x", &registry).is_err());
+        assert!(BlockState::parse("marble", &registry).is_err());
+    }
+}
@@ -1,17 +1,15 @@
-use cgmath::{Rad, Angle, PerspectiveFov};
+use cgmath::{Rad, Deg, Angle, PerspectiveFov};
 use cgmath::Matrix4;
 use collision::Frustum;
-use std;
 use collision;
-use glutin;
 use space::{Position, Direction};
 use block::cube_at;
-use default;
+use settings::{CameraSettings, Control};
 
-const DEFAULT_ASPECT_RATIO: f32 = default::VIEWPORT_WIDTH as f32 / default::VIEWPORT_HEIGHT as f32;
-const DEFAULT_FIELD_OF_VIEW: Rad<f32> = Rad(std::f32::consts::PI / 2.0 * (7.0 / 9.0));
-const DEFAULT_Z_NEAR_CUTOFF: f32 = 0.1;
-const DEFAULT_Z_FAR_CUTOFF: f32 = 1024.0;
+/// Upper bound for the move speed when speeding up
+pub const MAX_MOVE_SPEED: f32 = 1.0;
+/// Lower bound for the move speed when slowing down
+const MIN_MOVE_SPEED: f32 = 0.1;
 
 pub struct CameraState {
     perspective_fov: PerspectiveFov<f32>,
@@ -21,6 +19,8 @@ pub struct CameraState {
 
     move_speed: f32,
     rotation_speed: f32,
+    /// Whether solid blocks stop the camera, rather than letting it fly through
+    collision: bool,
 
     moving_up: bool,
     moving_left: bool,
@@ -35,19 +35,20 @@ pub struct CameraState {
 }
 
 impl CameraState {
-    pub fn new() -> CameraState {
+    pub fn new(settings: &CameraSettings, aspect: f32) -> CameraState {
         let mut camera = CameraState {
             perspective_fov: PerspectiveFov {
-                fovy: DEFAULT_FIELD_OF_VIEW,
-                aspect: DEFAULT_ASPECT_RATIO,
-                near: DEFAULT_Z_NEAR_CUTOFF,
-                far: DEFAULT_Z_FAR_CUTOFF,
+                fovy: Rad::from(Deg(settings.field_of_view)),
+                aspect,
+                near: settings.z_near,
+                far: settings.z_far,
             },
             perspective: [[0.0; 4]; 4].into(),
             position: [0.0, 16.0, 0.0].into(),
             direction: [0.0, -1.0, -1.0].into(),
-            move_speed: 0.3,
-            rotation_speed: 0.08,
+            move_speed: settings.move_speed,
+            rotation_speed: settings.rotation_speed,
+            collision: true,
             moving_up: false,
             moving_left: false,
             moving_down: false,
@@ -63,6 +64,15 @@ impl CameraState {
         camera
     }
 
+    pub fn move_speed(&self) -> f32 {
+        self.move_speed
+    }
+
+    pub fn set_aspect_ratio(&mut self, aspect: f32) {
+        self.perspective_fov.aspect = aspect;
+        self.update_perspective();
+    }
+
     fn update_perspective(&mut self) {
         let f = 1.0 / (self.perspective_fov.fovy / 2.0).tan();
         self.perspective = Matrix4::new(
@@ -109,7 +119,10 @@ impl CameraState {
         )
     }
 
-    pub fn update(&mut self) {
+    /// Move and turn the camera by whatever controls are held down - unless collision is turned
+    /// off, it slides along any solid block (as told by `is_solid`) it would otherwise move into
+    pub fn update<F>(&mut self, is_solid: F) where F: Fn(Position) -> bool {
+        let start = self.position;
         let f = {
             let f = &self.direction;
             let len = f[0] * f[0] + f[1] * f[1] + f[2] * f[2];
@@ -192,6 +205,19 @@ impl CameraState {
             self.position[1] -= f.1 * self.move_speed;
             self.position[2] -= f.2 * self.move_speed;
         }
+
+        // never trap the camera in a block it's already inside, e.g. after turning collision back on
+        if self.collision && is_solid(self.position) && !is_solid(start) {
+            let target = self.position;
+            self.position = start;
+            for axis in 0..3 {
+                let mut position = self.position;
+                position[axis] = target[axis];
+                if !is_solid(position) {
+                    self.position = position;
+                }
+            }
+        }
     }
 
     pub fn can_see(&self, position: Position) -> bool {
@@ -208,28 +234,32 @@ impl CameraState {
         false
     }
 
-    pub fn process_input(&mut self, pressed: bool, key: glutin::VirtualKeyCode) {
-        match key {
-            glutin::VirtualKeyCode::Space => self.moving_up = pressed,
-            glutin::VirtualKeyCode::LControl => self.moving_down = pressed,
-            glutin::VirtualKeyCode::A => self.moving_left = pressed,
-            glutin::VirtualKeyCode::D => self.moving_right = pressed,
-            glutin::VirtualKeyCode::W => self.moving_forward = pressed,
-            glutin::VirtualKeyCode::S => self.moving_backward = pressed,
-            glutin::VirtualKeyCode::Left => self.rotating_left = pressed,
-            glutin::VirtualKeyCode::Right => self.rotating_right = pressed,
-            glutin::VirtualKeyCode::Up => self.rotating_up = pressed,
-            glutin::VirtualKeyCode::Down => self.rotating_down = pressed,
-            glutin::VirtualKeyCode::Q => {
+    pub fn process_input(&mut self, pressed: bool, control: Control) {
+        match control {
+            Control::MoveUp => self.moving_up = pressed,
+            Control::MoveDown => self.moving_down = pressed,
+            Control::MoveLeft => self.moving_left = pressed,
+            Control::MoveRight => self.moving_right = pressed,
+            Control::MoveForward => self.moving_forward = pressed,
+            Control::MoveBackward => self.moving_backward = pressed,
+            Control::RotateLeft => self.rotating_left = pressed,
+            Control::RotateRight => self.rotating_right = pressed,
+            Control::RotateUp => self.rotating_up = pressed,
+            Control::RotateDown => self.rotating_down = pressed,
+            Control::ToggleCollision if pressed => {
+                self.collision = !self.collision;
+                info!("collision turned {}", if self.collision { "on" } else { "off" });
+            },
+            Control::SpeedUp => {
                 self.move_speed += self.move_speed + 0.1;
-                if self.move_speed > 1.0 {
-                    self.move_speed = 1.0;
+                if self.move_speed > MAX_MOVE_SPEED {
+                    self.move_speed = MAX_MOVE_SPEED;
                 }
             },
-            glutin::VirtualKeyCode::E => {
+            Control::SlowDown => {
                 self.move_speed = self.move_speed - 0.1;
-                if self.move_speed < 0.1 {
-                    self.move_speed = 0.1;
+                if self.move_speed < MIN_MOVE_SPEED {
+                    self.move_speed = MIN_MOVE_SPEED;
                 }
             },
             _ => (),
@@ -9,3 +9,13 @@ pub fn get_random_color() -> Color {
 }
 */
 pub const SKY: Color = [0.529, 0.808, 0.980];
+
+/// Colour `t` of the way from `a` to `b`
+pub fn lerp(a: Color, b: Color, t: f32) -> Color {
+    [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t, a[2] + (b[2] - a[2]) * t]
+}
+
+/// Colour with every component multiplied by `factor`, e.g. to darken it
+pub fn scale(color: Color, factor: f32) -> Color {
+    [color[0] * factor, color[1] * factor, color[2] * factor]
+}
@@ -0,0 +1,113 @@
+//! Commands typed into the terminal the game was started from, e.g. `time set noon`
+use std::io;
+use std::io::BufRead;
+use std::sync::mpsc;
+use std::thread;
+use settings::FogMode;
+use time;
+
+pub const HELP: &str = "\
+commands:
+    time                  print the time of day
+    time set <time>       jump to a time of day - in ticks, or dawn, noon, dusk or midnight
+    time add <ticks>      move time forward
+    time freeze           stop time
+    time unfreeze         start time again
+    fog                   print the fog settings
+    fog <mode>            change the fog to off, linear or exponential
+    fog start <fraction>  start linear fog this far toward the render distance, from 0 to 1
+    fog density <density> thicken exponential fog faster or slower
+    help                  print this message";
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Command {
+    Help,
+    Time,
+    SetTime(u64),
+    AddTime(u64),
+    FreezeTime(bool),
+    Fog,
+    SetFogMode(FogMode),
+    SetFogStart(f32),
+    SetFogDensity(f32),
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["help"] => Ok(Command::Help),
+            ["time"] => Ok(Command::Time),
+            ["time", "set", time] => time::parse_time_of_day(time).map(Command::SetTime),
+            ["time", "add", ticks] => ticks.parse().map(Command::AddTime).map_err(|_| format!("invalid number of ticks {:?}", ticks)),
+            ["time", "freeze"] => Ok(Command::FreezeTime(true)),
+            ["time", "unfreeze"] => Ok(Command::FreezeTime(false)),
+            ["fog"] => Ok(Command::Fog),
+            ["fog", "start", start] => match start.parse::<f32>() {
+                Ok(start) if (0.0..1.0).contains(&start) => Ok(Command::SetFogStart(start)),
+                _ => Err(format!("invalid fog start {:?} - must be at least 0 and less than 1", start)),
+            },
+            ["fog", "density", density] => match density.parse::<f32>() {
+                Ok(density) if density > 0.0 => Ok(Command::SetFogDensity(density)),
+                _ => Err(format!("invalid fog density {:?} - must be positive", density)),
+            },
+            ["fog", mode] => FogMode::from_name(mode).map(Command::SetFogMode)
+                .ok_or_else(|| format!("unknown fog mode {:?} - expected off, linear or exponential", mode)),
+            _ => Err(format!("unknown command {:?} - try help", line.trim())),
+        }
+    }
+}
+
+/// Reads commands from standard input in the background, so the game doesn't wait for them
+pub struct Console {
+    lines: mpsc::Receiver<String>,
+}
+
+impl Console {
+    pub fn start() -> Console {
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            let stdin = io::stdin();
+            for line in stdin.lock().lines() {
+                // stops once standard input closes, or the game has exited
+                match line {
+                    Ok(line) => if sender.send(line).is_err() { break },
+                    Err(_) => break,
+                }
+            }
+        });
+        Console { lines }
+    }
+
+    /// Every command typed since last asked, skipping blank lines
+    pub fn commands(&self) -> Vec<Result<Command, String>> {
+        self.lines.try_iter()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| Command::parse(&line))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use console::Command;
+    use settings::FogMode;
+    use time::NOON;
+
+    #[test]
+    fn console_parses_commands() {
+        assert_eq!(Command::parse("time set noon"), Ok(Command::SetTime(NOON)));
+        assert_eq!(Command::parse("  time   add 600 "), Ok(Command::AddTime(600)));
+        assert_eq!(Command::parse("time freeze"), Ok(Command::FreezeTime(true)));
+        assert_eq!(Command::parse("time"), Ok(Command::Time));
+        assert!(Command::parse("time set teatime").is_err());
+        assert!(Command::parse("time add -5").is_err());
+        assert!(Command::parse("weather rain").is_err());
+
+        assert_eq!(Command::parse("fog exponential"), Ok(Command::SetFogMode(FogMode::Exponential)));
+        assert_eq!(Command::parse("fog start 0.25"), Ok(Command::SetFogStart(0.25)));
+        assert!(Command::parse("fog start 1.5").is_err());
+        assert!(Command::parse("fog density 0").is_err());
+        assert!(Command::parse("fog thick").is_err());
+    }
+}
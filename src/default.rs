@@ -1,7 +1,30 @@
-/// Default settings go here
+//! Default settings go here
+//!
+//! These are used for anything not set in the settings file (see `settings.rs`)
+use color;
+use color::Color;
 
-pub const RENDER_DISTANCE_U8: u8 = 2;
-pub const RENDER_DISTANCE_I32: i32 = 2;
+pub const RENDER_DISTANCE: u8 = 2;
 
 pub const VIEWPORT_WIDTH: u32 = 1024;
 pub const VIEWPORT_HEIGHT: u32 = 768;
+
+/// Vertical field of view (in degrees)
+pub const FIELD_OF_VIEW: f32 = 70.0;
+pub const Z_NEAR_CUTOFF: f32 = 0.1;
+pub const Z_FAR_CUTOFF: f32 = 1024.0;
+
+pub const MOVE_SPEED: f32 = 0.3;
+pub const ROTATION_SPEED: f32 = 0.08;
+
+pub const LOG_LEVEL: &str = "debug";
+
+pub const SKY_COLOR: Color = color::SKY;
+
+/// Where linear fog starts, as a fraction of the render distance
+pub const FOG_START: f32 = 0.6;
+/// How thick exponential fog is at the render distance
+pub const FOG_DENSITY: f32 = 4.0;
+
+/// Name of the world generator used unless the settings pick another
+pub const WORLD_GENERATOR: &str = "natural";
@@ -1,13 +1,20 @@
 use world;
-use world::World;
+use time;
+use time::WorldTime;
 
 /// TODO: should be along the lines of `world: W where W: world::World`
 pub struct Game {
     pub world: world::InMemoryWorld,
+    pub time: WorldTime,
 }
 
 impl Game {
-    pub fn new() -> Game {
-        Game { world: world::InMemoryWorld::new() }
+    pub fn new(world: world::InMemoryWorld) -> Game {
+        Game { world, time: WorldTime::new(time::NOON) }
+    }
+
+    /// Advance the game by one fixed timestep
+    pub fn tick(&mut self) {
+        self.time.tick();
     }
 }
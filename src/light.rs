@@ -0,0 +1,380 @@
+//! Light levels of every loaded block - sky light falling from open sky, and block light given off
+//! by emissive blocks - spread by flood filling outward from where it comes from
+//!
+//! Light goes down a level with every block it travels, except sky light at full strength, which
+//! falls straight down as far as it can. Whole opaque blocks stop it.
+use std::collections::{HashMap, VecDeque};
+use cgmath::{Point3, Vector3};
+use block::{BlockType, MAX_EMISSION};
+use space::Adjacent;
+use world::{BlockCoordinates, ChunkCoordinates, HashChunk, Chunk, CHUNK_SIZE, split_block_position};
+
+/// Brightest light can be - full daylight, or next to the brightest lamp
+pub const MAX_LIGHT: u8 = MAX_EMISSION;
+
+/// Position of a block in the world, in blocks
+pub type BlockPosition = Point3<i32>;
+
+/// How brightly lit a block is, from 0 to MAX_LIGHT, by the sky and by emissive blocks
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Light {
+    pub sky: u8,
+    pub block: u8,
+}
+
+impl Light {
+    /// Light in chunks which haven't been lit yet - assumed to be open sky, so the edge of the
+    /// loaded world isn't drawn black
+    pub const OPEN_SKY: Light = Light { sky: MAX_LIGHT, block: 0 };
+
+    /// Both levels from 0 to 1, to be passed to shaders
+    pub fn to_attribute(self) -> [f32; 2] {
+        [self.sky as f32 / MAX_LIGHT as f32, self.block as f32 / MAX_LIGHT as f32]
+    }
+}
+
+/// The light falling on each face of a block, and on faces within it (e.g. the top of a slab)
+pub struct FaceLight {
+    pub up: Light,
+    pub down: Light,
+    pub north: Light,
+    pub south: Light,
+    pub east: Light,
+    pub west: Light,
+    pub inside: Light,
+}
+
+/// Sky and block light are spread separately, by the same rules
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Channel {
+    Sky,
+    Block,
+}
+
+const CHANNELS: [Channel; 2] = [Channel::Sky, Channel::Block];
+
+const CHUNK_VOLUME: usize = CHUNK_SIZE as usize * CHUNK_SIZE as usize * CHUNK_SIZE as usize;
+
+/// Light of every block in every loaded chunk
+///
+/// Chunks which haven't been lit have no light stored, and count as open sky (`Light::OPEN_SKY`) -
+/// the world renders them that way, and the chunk below takes full sky light from them. Light
+/// never spreads into an unlit chunk, but does spread out of a newly lit chunk into its
+/// neighbours, and from them into it.
+pub struct LightMap {
+    /// Sky light in the high four bits of each block, block light in the low four
+    chunks: HashMap<ChunkCoordinates, Vec<u8>>,
+}
+
+impl LightMap {
+    pub fn new() -> LightMap {
+        LightMap { chunks: HashMap::new() }
+    }
+
+    /// Light at `position`, if its chunk has been lit
+    pub fn get(&self, position: BlockPosition) -> Option<Light> {
+        let (chunk, block) = split_block_position(position);
+        self.chunks.get(&chunk).map(|levels| {
+            let packed = levels[index(block)];
+            Light { sky: packed >> 4, block: packed & 0xf }
+        })
+    }
+
+    fn level(&self, position: BlockPosition, channel: Channel) -> Option<u8> {
+        self.get(position).map(|light| match channel {
+            Channel::Sky => light.sky,
+            Channel::Block => light.block,
+        })
+    }
+
+    fn set_level(&mut self, position: BlockPosition, channel: Channel, level: u8) {
+        let (chunk, block) = split_block_position(position);
+        if let Some(levels) = self.chunks.get_mut(&chunk) {
+            let packed = &mut levels[index(block)];
+            *packed = match channel {
+                Channel::Sky => (*packed & 0xf) | (level << 4),
+                Channel::Block => (*packed & 0xf0) | level,
+            };
+        }
+    }
+
+    /// Light the chunk at `coordinates`, which must already be in `chunks`, spreading light
+    /// between it and its lit neighbours
+    ///
+    /// Until a chunk has been lit, its neighbours below assume it's open sky - any sky light
+    /// it now blocks is taken away from them.
+    pub fn add_chunk(&mut self, coordinates: ChunkCoordinates, chunks: &HashMap<ChunkCoordinates, HashChunk>) {
+        self.chunks.insert(coordinates, vec![0; CHUNK_VOLUME]);
+        let origin = chunk_origin(coordinates);
+        let size = CHUNK_SIZE as i32;
+        for channel in CHANNELS.iter().cloned() {
+            let mut queue = VecDeque::new();
+            for x in 0..size {
+                for y in 0..size {
+                    for z in 0..size {
+                        let position = origin + Vector3::new(x, y, z);
+                        let source = self.source(position, channel, chunks);
+                        if source > 0 {
+                            self.set_level(position, channel, source);
+                            queue.push_back(position);
+                        }
+                    }
+                }
+            }
+            self.spread(channel, queue, chunks);
+
+            let below = coordinates + Vector3::new(0, -1, 0);
+            let mut refill = VecDeque::new();
+            if channel == Channel::Sky && self.chunks.contains_key(&below) {
+                let mut removals = VecDeque::new();
+                for x in 0..size {
+                    for z in 0..size {
+                        let top_of_below = origin + Vector3::new(x, -1, z);
+                        if self.level(top_of_below, channel) == Some(MAX_LIGHT) && self.level(top_of_below + Vector3::new(0, 1, 0), channel) != Some(MAX_LIGHT) {
+                            self.set_level(top_of_below, channel, 0);
+                            removals.push_back((top_of_below, MAX_LIGHT));
+                        }
+                    }
+                }
+                refill = self.unspread(channel, removals, chunks);
+            }
+            // light shining in from every neighbour
+            for x in -1..size + 1 {
+                for y in -1..size + 1 {
+                    for z in -1..size + 1 {
+                        let outside = [x, y, z].iter().filter(|&&c| c < 0 || c >= size).count();
+                        if outside == 1 {
+                            refill.push_back(origin + Vector3::new(x, y, z));
+                        }
+                    }
+                }
+            }
+            self.spread(channel, refill, chunks);
+        }
+    }
+
+    /// Forget the light of a chunk which has been unloaded
+    #[allow(dead_code)]
+    pub fn remove_chunk(&mut self, coordinates: ChunkCoordinates) {
+        self.chunks.remove(&coordinates);
+    }
+
+    /// Relight around `position` after the block there has changed in `chunks` - taking away
+    /// light which came through it, and letting light spread into or out of it
+    pub fn block_changed(&mut self, position: BlockPosition, chunks: &HashMap<ChunkCoordinates, HashChunk>) {
+        for channel in CHANNELS.iter().cloned() {
+            let old = match self.level(position, channel) {
+                Some(old) => old,
+                None => return,
+            };
+            self.set_level(position, channel, 0);
+            let mut refill = self.unspread(channel, vec![(position, old)].into_iter().collect(), chunks);
+            let source = self.source(position, channel, chunks);
+            if source > 0 {
+                self.set_level(position, channel, source);
+                refill.push_back(position);
+            }
+            refill.extend(position.directly_adjacent());
+            self.spread(channel, refill, chunks);
+        }
+    }
+
+    /// Light given off at `position` regardless of its neighbours - emission for block light, and
+    /// full sky light at the top of a column with no lit chunk above it
+    fn source(&self, position: BlockPosition, channel: Channel, chunks: &HashMap<ChunkCoordinates, HashChunk>) -> u8 {
+        match channel {
+            Channel::Block => block_at(position, chunks).map_or(0, |block_type| block_type.emission),
+            Channel::Sky => {
+                let above = position + Vector3::new(0, 1, 0);
+                let open = !self.chunks.contains_key(&split_block_position(above).0);
+                if open && lets_light_through(position, chunks) { MAX_LIGHT } else { 0 }
+            },
+        }
+    }
+
+    /// Flood fill outward from every position in `queue`
+    fn spread(&mut self, channel: Channel, mut queue: VecDeque<BlockPosition>, chunks: &HashMap<ChunkCoordinates, HashChunk>) {
+        while let Some(position) = queue.pop_front() {
+            let level = self.level(position, channel).unwrap_or(0);
+            if level == 0 {
+                continue;
+            }
+            for neighbour in position.directly_adjacent() {
+                let current = match self.level(neighbour, channel) {
+                    Some(current) => current,
+                    None => continue,
+                };
+                if !lets_light_through(neighbour, chunks) {
+                    continue;
+                }
+                let spread = if falls_straight_down(channel, level, position, neighbour) { level } else { level - 1 };
+                if spread > current {
+                    self.set_level(neighbour, channel, spread);
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+    }
+
+    /// Take away light which spread from the positions in `removals`, which have already been
+    /// darkened and are given with the level they had - returns the positions still lit from
+    /// elsewhere, from which light needs to be spread again to fill the gap
+    fn unspread(&mut self, channel: Channel, mut removals: VecDeque<(BlockPosition, u8)>, chunks: &HashMap<ChunkCoordinates, HashChunk>) -> VecDeque<BlockPosition> {
+        let mut refill = VecDeque::new();
+        while let Some((position, level)) = removals.pop_front() {
+            for neighbour in position.directly_adjacent() {
+                let current = match self.level(neighbour, channel) {
+                    Some(current) if current > 0 => current,
+                    _ => continue,
+                };
+                if current < level || (current == level && falls_straight_down(channel, level, position, neighbour)) {
+                    self.set_level(neighbour, channel, 0);
+                    removals.push_back((neighbour, current));
+                    // a light source keeps shining, whatever was around it
+                    let source = self.source(neighbour, channel, chunks);
+                    if source > 0 {
+                        self.set_level(neighbour, channel, source);
+                        refill.push_back(neighbour);
+                    }
+                } else {
+                    refill.push_back(neighbour);
+                }
+            }
+        }
+        refill
+    }
+}
+
+/// Where each block is in a chunk's light levels
+fn index(block: BlockCoordinates) -> usize {
+    let size = CHUNK_SIZE as usize;
+    (block[0] as usize * size + block[1] as usize) * size + block[2] as usize
+}
+
+fn chunk_origin(coordinates: ChunkCoordinates) -> BlockPosition {
+    let size = CHUNK_SIZE as i32;
+    [coordinates[0] * size, coordinates[1] * size, coordinates[2] * size].into()
+}
+
+fn block_at(position: BlockPosition, chunks: &HashMap<ChunkCoordinates, HashChunk>) -> Option<&'static BlockType> {
+    let (chunk, block) = split_block_position(position);
+    chunks.get(&chunk).and_then(|chunk| chunk.get(block).cloned())
+}
+
+fn lets_light_through(position: BlockPosition, chunks: &HashMap<ChunkCoordinates, HashChunk>) -> bool {
+    block_at(position, chunks).is_none_or(|block_type| !block_type.blocks_light())
+}
+
+/// Whether light going from `from` to `to` is full sky light falling, which doesn't fade
+fn falls_straight_down(channel: Channel, level: u8, from: BlockPosition, to: BlockPosition) -> bool {
+    channel == Channel::Sky && level == MAX_LIGHT && to[1] == from[1] - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use block;
+    use block::BlockType;
+    use light::{LightMap, Light, BlockPosition, MAX_LIGHT};
+    use world::{Chunk, ChunkCoordinates, HashChunk, CHUNK_SIZE};
+
+    static LAMP: &BlockType = &BlockType { emission: 12, ..*block::STONE };
+
+    fn position(x: i32, y: i32, z: i32) -> BlockPosition {
+        [x, y, z].into()
+    }
+
+    /// Chunks below y = 0 filled with stone, with a cave in the top one - a tunnel from (1, -8, 1)
+    /// to (10, -8, 1), open to the sky at its west end - and a hole in the top of the one below
+    fn cave() -> HashMap<ChunkCoordinates, HashChunk> {
+        let mut chunks = HashMap::new();
+        let size = CHUNK_SIZE;
+        for &coordinates in [[0, -1, 0], [0, -2, 0]].iter() {
+            let mut chunk = HashChunk::new();
+            for x in 0..size {
+                for y in 0..size {
+                    for z in 0..size {
+                        chunk.set([x, y, z].into(), block::STONE);
+                    }
+                }
+            }
+            chunks.insert(coordinates.into(), chunk);
+        }
+        let top = chunks.get_mut(&[0, -1, 0].into()).unwrap();
+        for x in 1..11 {
+            top.remove([x, 24, 1].into());
+        }
+        for y in 25..32 {
+            top.remove([1, y, 1].into());
+        }
+        chunks.get_mut(&[0, -2, 0].into()).unwrap().remove([1, 31, 1].into());
+        chunks
+    }
+
+    #[test]
+    fn light_sky_falls_and_fades() {
+        let chunks = cave();
+        let mut light = LightMap::new();
+        light.add_chunk([0, -1, 0].into(), &chunks);
+        // straight down the shaft at full strength, then fading along the tunnel
+        assert_eq!(light.get(position(1, -8, 1)), Some(Light { sky: MAX_LIGHT, block: 0 }));
+        assert_eq!(light.get(position(5, -8, 1)).unwrap().sky, MAX_LIGHT - 4);
+        assert_eq!(light.get(position(4, -1, 4)).unwrap().sky, 0);
+        // the chunk below hasn't been lit
+        assert_eq!(light.get(position(1, -40, 1)), None);
+    }
+
+    #[test]
+    fn light_chunk_above_blocks_sky() {
+        let mut chunks = cave();
+        let mut light = LightMap::new();
+        light.add_chunk([0, -2, 0].into(), &chunks);
+        // assumed open to the sky until the chunk above is lit
+        assert_eq!(light.get(position(1, -33, 1)).unwrap().sky, MAX_LIGHT);
+        light.add_chunk([0, -1, 0].into(), &chunks);
+        assert_eq!(light.get(position(1, -33, 1)).unwrap().sky, 0);
+
+        // a roof over the shaft cuts off the sky, all the way along the tunnel
+        let top = chunks.get_mut(&[0, -1, 0].into()).unwrap();
+        top.set([1, 31, 1].into(), block::STONE);
+        light.block_changed(position(1, -1, 1), &chunks);
+        assert_eq!(light.get(position(1, -8, 1)).unwrap().sky, 0);
+        assert_eq!(light.get(position(5, -8, 1)).unwrap().sky, 0);
+
+        let top = chunks.get_mut(&[0, -1, 0].into()).unwrap();
+        top.remove([1, 31, 1].into());
+        light.block_changed(position(1, -1, 1), &chunks);
+        assert_eq!(light.get(position(5, -8, 1)).unwrap().sky, MAX_LIGHT - 4);
+    }
+
+    #[test]
+    fn light_from_blocks_across_chunks() {
+        let mut chunks = cave();
+        let mut light = LightMap::new();
+        light.add_chunk([0, -1, 0].into(), &chunks);
+        // a lamp at the end of the tunnel, lighting it and nothing behind the stone
+        let top = chunks.get_mut(&[0, -1, 0].into()).unwrap();
+        top.set([10, 24, 1].into(), LAMP);
+        light.block_changed(position(10, -8, 1), &chunks);
+        assert_eq!(light.get(position(10, -8, 1)).unwrap().block, 12);
+        assert_eq!(light.get(position(7, -8, 1)).unwrap().block, 9);
+        assert_eq!(light.get(position(10, -8, 3)).unwrap().block, 0);
+
+        let top = chunks.get_mut(&[0, -1, 0].into()).unwrap();
+        top.remove([10, 24, 1].into());
+        light.block_changed(position(10, -8, 1), &chunks);
+        assert_eq!(light.get(position(7, -8, 1)).unwrap().block, 0);
+
+        // light spreads into a chunk lit after it, and back out of it
+        chunks.get_mut(&[0, -1, 0].into()).unwrap().remove([31, 31, 0].into());
+        light.block_changed(position(31, -1, 0), &chunks);
+        let mut lamp = HashChunk::new();
+        lamp.set([0, 31, 0].into(), LAMP);
+        chunks.insert([1, -1, 0].into(), lamp);
+        light.add_chunk([1, -1, 0].into(), &chunks);
+        assert_eq!(light.get(position(33, -1, 0)).unwrap().block, 11);
+        assert_eq!(light.get(position(31, -1, 0)).unwrap().block, 11);
+        assert_eq!(light.get(position(30, -1, 0)).unwrap().block, 0);
+        assert_eq!(light.get(position(31, 0, 0)), None);
+    }
+}
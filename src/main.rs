@@ -8,9 +8,19 @@ extern crate noise;
 #[macro_use]
 extern crate log;
 extern crate simplelog;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate toml;
+extern crate image;
 
 mod render;
+mod atlas;
 mod block;
+mod block_registry;
+mod block_state;
+mod shape;
+mod light;
 mod camera;
 mod space;
 mod color;
@@ -18,38 +28,96 @@ mod world;
 mod worldgen;
 mod game;
 mod default;
+mod settings;
+mod tools;
+mod map;
+mod time;
+mod console;
 
 use glium::Surface;
 use world::World;
 use std::thread;
+use std::env;
+use std::process;
+use std::cmp::Ordering;
+use std::path::PathBuf;
+use cgmath::MetricSpace;
 use std::time::{Duration, Instant};
 use glutin::ElementState::Pressed;
 use glutin::WindowEvent::{Closed, Resized, KeyboardInput};
 
-use simplelog::{Config, TermLogger, CombinedLogger, LogLevelFilter};
+use simplelog::{Config, TermLogger, CombinedLogger};
+use console::Command;
 
 /// Global, thread-safe context for the application
 struct Application {
     pub display: glium::Display,
     pub camera: camera::CameraState,
     pub game: game::Game,
+    pub settings: settings::Settings,
+    /// Whether settings have changed since they were loaded, and so need writing back
+    pub settings_changed: bool,
 }
 
 impl Application {
-    pub fn new(events_loop: &glutin::EventsLoop) -> Application {
+    pub fn new(events_loop: &glutin::EventsLoop, settings: settings::Settings, world: world::InMemoryWorld) -> Application {
         let window = glutin::WindowBuilder::new()
-            .with_dimensions(default::VIEWPORT_WIDTH, default::VIEWPORT_HEIGHT)
+            .with_dimensions(settings.window.width, settings.window.height)
             .with_title("Ave");
         let context = glutin::ContextBuilder::new()
             .with_depth_buffer(24)
             .with_vsync(true);
         let display = glium::Display::new(window, context, events_loop).unwrap();
-        let camera = camera::CameraState::new();
-        let game = game::Game::new();
+        let aspect = settings.window.width as f32 / settings.window.height as f32;
+        let camera = camera::CameraState::new(&settings.camera, aspect);
+        let game = game::Game::new(world);
         Application {
             display,
             camera,
             game,
+            settings,
+            settings_changed: false,
+        }
+    }
+
+    /// Carry out a command typed into the console
+    pub fn execute(&mut self, command: Command) {
+        let time = &mut self.game.time;
+        let fog = &mut self.settings.render.fog;
+        match command {
+            Command::Help => println!("{}", console::HELP),
+            Command::Time | Command::Fog => {},
+            Command::SetTime(time_of_day) => time.set_time_of_day(time_of_day),
+            Command::AddTime(ticks) => time.add(ticks),
+            Command::FreezeTime(frozen) => time.set_frozen(frozen),
+            Command::SetFogMode(mode) => fog.mode = mode,
+            Command::SetFogStart(start) => fog.start = start,
+            Command::SetFogDensity(density) => fog.density = density,
+        }
+        match command {
+            Command::Help => {},
+            Command::Time | Command::SetTime(_) | Command::AddTime(_) | Command::FreezeTime(_) => {
+                info!("time of day is {}{}", time.time_of_day(), if time.is_frozen() { " (frozen)" } else { "" });
+            },
+            Command::Fog | Command::SetFogMode(_) | Command::SetFogStart(_) | Command::SetFogDensity(_) => {
+                // fog changed at runtime is kept, like other settings
+                self.settings_changed |= command != Command::Fog;
+                info!("fog is {}, starting {} of the way to the render distance, with density {}", fog.mode.name(), fog.start, fog.density);
+            },
+        }
+    }
+
+    /// Write settings back to disk if anything was changed at runtime
+    pub fn save_settings(&mut self) {
+        if !self.settings_changed {
+            return;
+        }
+        match self.settings.save() {
+            Ok(()) => {
+                info!("Saved settings to {}", self.settings.path().display());
+                self.settings_changed = false;
+            },
+            Err(e) => error!("Couldn't save settings: {}", e),
         }
     }
 }
@@ -59,7 +127,9 @@ pub enum Action {
     Continue,
 }
 
-pub fn start_loop<F>(mut callback: F) where F: FnMut() -> Action {
+/// Call `callback` every frame with the number of fixed timesteps since the last frame, for it to
+/// advance the game by
+pub fn start_loop<F>(mut callback: F) where F: FnMut(u32) -> Action {
     let mut accumulator = Duration::new(0, 0);
     let mut previous_clock = Instant::now();
 
@@ -67,9 +137,10 @@ pub fn start_loop<F>(mut callback: F) where F: FnMut() -> Action {
     let mut ticks_per_second;
     let mut this_second = Duration::new(0, 0);
     let mut ticks_this_second = 0;
+    let mut steps = 0;
 
     loop {
-        match callback() {
+        match callback(steps) {
             Action::Stop => break,
             Action::Continue => ()
         };
@@ -90,29 +161,77 @@ pub fn start_loop<F>(mut callback: F) where F: FnMut() -> Action {
         accumulator += time_passed;
 
         let fixed_time_stamp = Duration::new(0, 16666667);
+        steps = 0;
         while accumulator >= fixed_time_stamp {
             accumulator -= fixed_time_stamp;
-
-            // if you have a game, update the state here
+            steps += 1;
         }
 
         thread::sleep(fixed_time_stamp - accumulator);
     }
 }
 
+/// Load `shader`, falling back to the built in one if it can't be read or doesn't compile
+fn load_shader(shaders: &mut render::ShaderLoader, display: &glium::Display, shader: render::Shaders) -> glium::Program {
+    shaders.load(display, shader).unwrap_or_else(|e| {
+        error!("{} - using the built in shader instead", e);
+        render::ShaderLoader::new(None).load(display, shader).unwrap()
+    })
+}
+
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Some(result) = tools::run(&args) {
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let settings = settings::Settings::load(settings::DEFAULT_PATH).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
     CombinedLogger::init(
         vec![
-            TermLogger::new(LogLevelFilter::Debug, Config::default()).unwrap(),
+            TermLogger::new(settings.log_level(), Config::default()).unwrap(),
         ]
     ).unwrap();
+    let blocks = block_registry::BlockRegistry::load(block_registry::DEFAULT_PATH).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    info!("{} block types registered", blocks.len());
+    let atlas = atlas::TextureAtlas::from_directory(atlas::DEFAULT_PATH).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    for name in atlas.missing(&blocks) {
+        warn!("no texture {:?} in {}, using the block's colour instead", name, atlas::DEFAULT_PATH);
+    }
+    let metadata = world::WorldMetadata {
+        generator: settings.world.generator.clone(),
+        seed: settings.world.seed.unwrap_or_else(rand::random),
+        parameters: settings.world.generator_parameters(),
+    };
+    info!("generating world with {} generator, seed {}", metadata.generator, metadata.seed);
+    // generators can build worlds from any registered block, not just the built in ones
+    let generators = worldgen::GeneratorRegistry::with_builtins(blocks);
+    let world = world::InMemoryWorld::with_metadata(metadata, &generators).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
     let mut events_loop = glutin::EventsLoop::new();
-    let mut application = Application::new(&events_loop);
+    let mut application = Application::new(&events_loop, settings, world);
     application.display.gl_window().set_cursor_state(glutin::CursorState::Grab).expect("couldn't grab cursor");
     let mut cursor_grabbed = true;
 
-    let indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip);
-    let program = render::get_shader(&application.display, render::Shaders::Phong);
+    let indices = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
+    let mut shaders = render::ShaderLoader::new(application.settings.render.shader_directory.as_ref().map(PathBuf::from));
+    let mut shading = render::Shading::new(application.settings.render.shading);
+    let mut program = load_shader(&mut shaders, &application.display, shading.shader);
+    let atlas_texture = render::atlas_texture(&application.display, &atlas);
     let params = glium::DrawParameters {
         depth: glium::Depth {
             test: glium::draw_parameters::DepthTest::IfLess,
@@ -124,18 +243,89 @@ fn main() {
         smooth: Some(glium::draw_parameters::Smooth::Nicest),
         ..Default::default()
     };
-    let sky_color = (color::SKY[0], color::SKY[1], color::SKY[2], 1.0);
+    // translucent blocks are blended over what's already drawn, without hiding anything behind them
+    let translucent_params = glium::DrawParameters {
+        depth: glium::Depth {
+            test: glium::draw_parameters::DepthTest::IfLess,
+            write: false,
+            ..Default::default()
+        },
+        blend: glium::Blend::alpha_blending(),
+        ..params.clone()
+    };
+    let render_distance = application.settings.render.distance;
+    let console = console::Console::start();
 
-    start_loop(move || {
-        application.camera.update();
+    start_loop(move |steps| {
+        for command in console.commands() {
+            match command {
+                Ok(command) => application.execute(command),
+                Err(e) => warn!("{}", e),
+            }
+        }
+        for _ in 0..steps {
+            application.game.tick();
+        }
+        if shaders.changed(shading.shader) {
+            // a broken shader leaves the last one that worked in place
+            match shaders.load(&application.display, shading.shader) {
+                Ok(reloaded) => {
+                    program = reloaded;
+                    info!("reloaded {} shader", shading.shader.name());
+                },
+                Err(e) => error!("{}", e),
+            }
+        }
+        let world = &application.game.world;
+        application.camera.update(|position| world.is_solid(position));
         let mut target = application.display.draw();
-        target.clear_color_and_depth(sky_color, 1.0);
+        let sky = application.game.time.sky_color(application.settings.render.sky_color);
+        target.clear_color_and_depth((sky[0], sky[1], sky[2], 1.0), 1.0);
+        let (sun_direction, sun_color) = application.game.time.sun();
+        let sun_direction: [f32; 3] = sun_direction.into();
+        let daylight = application.game.time.daylight();
+        let fog = render::Fog::new(&application.settings.render.fog, render_distance);
+        let draw_params = glium::DrawParameters { polygon_mode: shading.polygon_mode, ..params.clone() };
+        let translucent_draw_params = glium::DrawParameters { polygon_mode: shading.polygon_mode, ..translucent_params.clone() };
         let perspective: [[f32; 4]; 4] = application.camera.perspective.into();
         let view: [[f32; 4]; 4] = application.camera.get_view().into();
+        // nearest filtering keeps pixel art sharp up close
+        let atlas_sampler = atlas_texture.sampled()
+            .magnify_filter(glium::uniforms::MagnifySamplerFilter::Nearest)
+            .minify_filter(glium::uniforms::MinifySamplerFilter::NearestMipmapLinear);
         let uniform = uniform! {
             model: space::MODEL,
             perspective: perspective,
-            view: view
+            view: view,
+            alpha: 1.0f32,
+            atlas: atlas_sampler,
+            mip_bias: render::MIP_BIAS,
+            sun_direction: sun_direction,
+            sun_color: sun_color,
+            daylight: daylight,
+            debug_view: shading.debug_view,
+            fog_mode: fog.mode,
+            fog_start: fog.start,
+            fog_end: fog.end,
+            fog_density: fog.density,
+            sky_color: sky
+        };
+        let translucent_uniform = uniform! {
+            model: space::MODEL,
+            perspective: perspective,
+            view: view,
+            alpha: render::TRANSLUCENT_ALPHA,
+            atlas: atlas_sampler,
+            mip_bias: render::MIP_BIAS,
+            sun_direction: sun_direction,
+            sun_color: sun_color,
+            daylight: daylight,
+            debug_view: shading.debug_view,
+            fog_mode: fog.mode,
+            fog_start: fog.start,
+            fog_end: fog.end,
+            fog_density: fog.density,
+            sky_color: sky
         };
 
         // generate chunks as we move the camera
@@ -143,9 +333,10 @@ fn main() {
         let cx = chunk_coords.x;
         let cy = chunk_coords.y;
         let cz = chunk_coords.z;
-        for x in (cx - default::RENDER_DISTANCE_I32)..(cx + default::RENDER_DISTANCE_I32) {
-            for y in (cy - default::RENDER_DISTANCE_I32)..(cy + default::RENDER_DISTANCE_I32) {
-                for z in (cz - default::RENDER_DISTANCE_I32)..(cz + default::RENDER_DISTANCE_I32) {
+        let distance = render_distance as i32;
+        for x in (cx - distance)..(cx + distance) {
+            for y in (cy - distance)..(cy + distance) {
+                for z in (cz - distance)..(cz + distance) {
                     application.game.world.get_or_create([x, y, z].into());
                 }
             }
@@ -153,25 +344,51 @@ fn main() {
 
         let mut nearby_blocks_count = 0;
         let mut blocks_rendered_count = 0;
-        for (position, block_type) in application.game.world.at(application.camera.position, default::RENDER_DISTANCE_U8) {
+        let mut translucent_blocks = Vec::new();
+        for (position, state) in application.game.world.at(application.camera.position, render_distance) {
             nearby_blocks_count += 1;
             if application.camera.can_see(position) {
                 blocks_rendered_count += 1;
-                let vertices = block::make_cube(&application.display, &position, block_type.color, block::Mask::new());
+                if !state.block_type.opaque {
+                    translucent_blocks.push((position, state));
+                    continue;
+                }
+                let mask = application.game.world.hidden_faces(position, state.block_type);
+                let light = application.game.world.face_light(position);
+                let vertices = block::make_block(&application.display, &position, state, &atlas, mask, light);
                 target.draw(
                     &vertices,
                     indices,
                     &program,
                     &uniform,
-                    &params
+                    &draw_params
                 ).unwrap()
             }
         }
+
+        // translucent blocks go after all opaque ones, furthest first so they blend correctly
+        let camera_position = application.camera.position;
+        translucent_blocks.sort_by(|a, b| {
+            camera_position.distance2(b.0).partial_cmp(&camera_position.distance2(a.0)).unwrap_or(Ordering::Equal)
+        });
+        for (position, state) in translucent_blocks {
+            let mask = application.game.world.hidden_faces(position, state.block_type);
+            let light = application.game.world.face_light(position);
+            let vertices = block::make_block(&application.display, &position, state, &atlas, mask, light);
+            target.draw(
+                &vertices,
+                indices,
+                &program,
+                &translucent_uniform,
+                &translucent_draw_params
+            ).unwrap()
+        }
         debug!("{} blocks rendered of {} blocks nearby", blocks_rendered_count, nearby_blocks_count);
 
         target.finish().unwrap();
 
         let mut action = Action::Continue;
+        let mut cycle_shading = false;
 
         // polling and handling the events received by the window
         events_loop.poll_events(|event| {
@@ -180,12 +397,19 @@ fn main() {
                     Closed => action = Action::Stop,
                     Resized(w, h) => {
                         info!("Window resized to {}px x {}px", w, h);
+                        if w > 0 && h > 0 {
+                            application.camera.set_aspect_ratio(w as f32 / h as f32);
+                            application.settings.window.width = w;
+                            application.settings.window.height = h;
+                            application.settings_changed = true;
+                        }
                     },
                     KeyboardInput { input, .. } => {
                         let pressed = input.state == Pressed;
-                        match input.virtual_keycode {
-                            Some(key) => match key {
-                                glutin::VirtualKeyCode::Escape => {
+                        let control = input.virtual_keycode.and_then(|key| application.settings.keys.control(key));
+                        match control {
+                            Some(control) => match control {
+                                settings::Control::ToggleCursor => {
                                     if pressed {
                                         if cursor_grabbed {
                                             application.display.gl_window().set_cursor_state(glutin::CursorState::Normal).expect("couldn't ungrab cursor");
@@ -196,7 +420,14 @@ fn main() {
                                         }
                                     }
                                 }
-                                _ => application.camera.process_input(pressed, key),
+                                settings::Control::CycleShading => cycle_shading |= pressed,
+                                _ => {
+                                    application.camera.process_input(pressed, control);
+                                    if application.camera.move_speed() != application.settings.camera.move_speed {
+                                        application.settings.camera.move_speed = application.camera.move_speed();
+                                        application.settings_changed = true;
+                                    }
+                                },
                             },
                             None => (),
                         };
@@ -207,6 +438,20 @@ fn main() {
             }
         });
 
+        if cycle_shading {
+            // always moves on, even if the shader is broken, so the next press tries another mode
+            let mode = application.settings.render.shading.next();
+            shading = render::Shading::new(mode);
+            program = load_shader(&mut shaders, &application.display, shading.shader);
+            application.settings.render.shading = mode;
+            application.settings_changed = true;
+            info!("shading mode is {}", mode.name());
+        }
+
+        if let Action::Stop = action {
+            application.save_settings();
+        }
+
         return action;
     });
 }
@@ -0,0 +1,143 @@
+//! Top down maps of generated terrain, for looking at worlds without opening a window
+use std::path::Path;
+use image::{Rgb, RgbImage};
+use block::BlockType;
+use color::Color;
+use world::CHUNK_SIZE;
+use worldgen::WorldGenerator;
+
+/// How much each block of height difference to the north west lightens or darkens a column
+const HILLSHADE: f32 = 0.15;
+
+/// Colour of columns with no blocks at all in the region
+const EMPTY: [u8; 3] = [0, 0, 0];
+
+/// A rectangle of chunks to map - horizontally from `min_x` to `max_x` and `min_z` to `max_z`, and
+/// vertically from `min_y` to `max_y`, all inclusive and in chunk coordinates
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Region {
+    pub min_x: i32,
+    pub max_x: i32,
+    pub min_y: i32,
+    pub max_y: i32,
+    pub min_z: i32,
+    pub max_z: i32,
+}
+
+impl Region {
+    /// Chunks within `radius` chunks of the origin horizontally, between `min_y` and `max_y`
+    pub fn around_origin(radius: i32, min_y: i32, max_y: i32) -> Region {
+        Region { min_x: -radius, max_x: radius, min_y, max_y, min_z: -radius, max_z: radius }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.max_x < self.min_x || self.max_y < self.min_y || self.max_z < self.min_z
+    }
+
+    /// Size of the region in blocks, east to west and north to south
+    fn size(&self) -> (usize, usize) {
+        let size = CHUNK_SIZE as usize;
+        ((self.max_x - self.min_x + 1) as usize * size, (self.max_z - self.min_z + 1) as usize * size)
+    }
+}
+
+/// The highest block of every column in a region
+pub struct TopDownMap {
+    width: usize,
+    depth: usize,
+    /// Rows of columns from north to south - the world height and type of each column's highest
+    /// block, if it has any
+    columns: Vec<Option<(i32, &'static BlockType)>>,
+}
+
+impl TopDownMap {
+    /// Generate every chunk in `region`, keeping the highest block of each column
+    pub fn generate(generator: &mut dyn WorldGenerator, region: Region) -> TopDownMap {
+        let (width, depth) = region.size();
+        let mut columns = vec![None; width * depth];
+        let size = CHUNK_SIZE as i32;
+        for cx in region.min_x..region.max_x + 1 {
+            for cz in region.min_z..region.max_z + 1 {
+                for cy in region.min_y..region.max_y + 1 {
+                    let chunk = generator.generate_chunk([cx, cy, cz].into());
+                    for (position, &block_type) in chunk.blocks.iter() {
+                        let x = ((cx - region.min_x) * size) as usize + position[0] as usize;
+                        let z = ((cz - region.min_z) * size) as usize + position[2] as usize;
+                        let y = cy * size + position[1] as i32;
+                        let column: &mut Option<(i32, &BlockType)> = &mut columns[z * width + x];
+                        if column.is_none_or(|(top, _)| y > top) {
+                            *column = Some((y, block_type));
+                        }
+                    }
+                }
+            }
+        }
+        TopDownMap { width, depth, columns }
+    }
+
+    fn column(&self, x: usize, z: usize) -> Option<(i32, &'static BlockType)> {
+        self.columns[z * self.width + x]
+    }
+
+    /// Colour each column by its highest block, lit from the north west - slopes facing the light
+    /// are lighter and slopes facing away darker
+    pub fn render(&self) -> RgbImage {
+        RgbImage::from_fn(self.width as u32, self.depth as u32, |x, z| {
+            let (x, z) = (x as usize, z as usize);
+            match self.column(x, z) {
+                Some((height, block_type)) => {
+                    // the edge of the map has nothing to compare with, so is left flat
+                    let north_west = if x > 0 && z > 0 { self.column(x - 1, z - 1) } else { None };
+                    let rise = north_west.map_or(0, |(other, _)| height - other);
+                    let shade = (1.0 + rise as f32 * HILLSHADE).clamp(0.5, 1.5);
+                    Rgb(to_rgb(block_type.color, shade))
+                },
+                None => Rgb(EMPTY),
+            }
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        self.render().save(path).map_err(|e| format!("couldn't write map {}: {}", path.display(), e))
+    }
+}
+
+fn to_rgb(color: Color, shade: f32) -> [u8; 3] {
+    let channel = |c: f32| ((c * shade).clamp(0.0, 1.0) * 255.0).round() as u8;
+    [channel(color[0]), channel(color[1]), channel(color[2])]
+}
+
+#[cfg(test)]
+mod tests {
+    use block;
+    use block_registry::BlockRegistry;
+    use map::{Region, TopDownMap, to_rgb};
+    use world::CHUNK_SIZE;
+    use worldgen::FlatWorldGenerator;
+
+    #[test]
+    fn map_colours_columns_by_highest_block() {
+        let mut generator = FlatWorldGenerator::from_preset("stone,2*grass", &BlockRegistry::default()).unwrap();
+        let map = TopDownMap::generate(&mut generator, Region::around_origin(1, -1, 0));
+        let image = map.render();
+        assert_eq!(image.dimensions(), (3 * CHUNK_SIZE as u32, 3 * CHUNK_SIZE as u32));
+        // flat ground has no slopes to shade
+        assert!(image.pixels().all(|pixel| pixel.0 == to_rgb(block::GRASS.color, 1.0)));
+
+        let empty = TopDownMap::generate(&mut generator, Region::around_origin(0, 1, 1)).render();
+        assert!(empty.pixels().all(|pixel| pixel.0 == [0, 0, 0]));
+    }
+
+    #[test]
+    fn map_hillshading() {
+        let column = |height| Some((height, block::GRASS));
+        let columns = vec![column(4), column(6), column(2), column(4), column(4), column(4), column(4), column(4)];
+        let image = TopDownMap { width: 4, depth: 2, columns }.render();
+        let flat = image.get_pixel(0, 1).0;
+        // below a peak to the north west, and above a dip
+        let shadowed = image.get_pixel(2, 1).0;
+        let lit = image.get_pixel(3, 1).0;
+        assert!(lit[1] > flat[1] && shadowed[1] < flat[1]);
+    }
+}
@@ -1,47 +1,239 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 use glium;
+use atlas::TextureAtlas;
+use settings::{FogMode, FogSettings, ShadingMode};
+use world::CHUNK_SIZE;
 
 #[derive(Copy, Clone)]
 pub struct Vertex {
     pub position: [f32; 3],
     pub color: [f32; 3],
     pub normal: [f32; 3],
+    /// Texture coordinates in the block texture atlas
+    pub uv: [f32; 2],
+    /// Sky and block light falling on the vertex, from 0 to 1
+    pub light: [f32; 2],
 }
 
 impl Vertex {
-    pub fn new(position: [f32; 3], color: [f32; 3], normal: [f32; 3]) -> Vertex {
-        Vertex {position, color, normal}
+    pub fn new(position: [f32; 3], color: [f32; 3], normal: [f32; 3], uv: [f32; 2], light: [f32; 2]) -> Vertex {
+        Vertex {position, color, normal, uv, light}
     }
 }
 
-implement_vertex!(Vertex, position, color, normal);
+implement_vertex!(Vertex, position, color, normal, uv, light);
 
-#[allow(dead_code)]
+/// Opacity of translucent blocks such as water
+pub const TRANSLUCENT_ALPHA: f32 = 0.6;
+
+/// Added to the mipmap level the atlas is sampled at - below zero keeps pixel art crisp from a
+/// distance, at the cost of some shimmering
+pub const MIP_BIAS: f32 = -0.5;
+
+/// Upload a texture atlas, with only as many mipmaps as keep its tiles from blending together
+pub fn atlas_texture<F>(display: &F, atlas: &TextureAtlas) -> glium::texture::Texture2d where F: glium::backend::Facade {
+    let image = atlas.image();
+    let raw = glium::texture::RawImage2d::from_raw_rgba_reversed(image, image.dimensions());
+    let mipmaps = glium::texture::MipmapsOption::AutoGeneratedMipmapsMax(atlas.mipmap_levels());
+    glium::texture::Texture2d::with_mipmaps(display, raw, mipmaps).unwrap()
+}
+
+/// Fog as passed to shaders - distances are in blocks from the camera
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Fog {
+    /// 0 for no fog, 1 for linear and 2 for exponential
+    pub mode: i32,
+    pub start: f32,
+    pub end: f32,
+    pub density: f32,
+}
+
+impl Fog {
+    /// Fog thickest at the edge of the rendered world, `render_distance` chunks away
+    pub fn new(settings: &FogSettings, render_distance: u8) -> Fog {
+        let end = render_distance as f32 * CHUNK_SIZE as f32;
+        let mode = match settings.mode {
+            FogMode::Off => 0,
+            FogMode::Linear => 1,
+            FogMode::Exponential => 2,
+        };
+        Fog { mode, start: settings.start * end, end, density: settings.density }
+    }
+}
+
+/// How often shaders loaded from a directory are checked for changes
+const SHADER_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Shaders {
     None,
     Gouraud,
     Phong,
+    /// Debug views, picked with the `debug_view` uniform
+    Debug,
+}
+
+impl Shaders {
+    /// Name of the shader's files, e.g. `phong.glslv` and `phong.glslf`
+    pub fn name(self) -> &'static str {
+        match self {
+            Shaders::None => "nolighting",
+            Shaders::Gouraud => "gouraud",
+            Shaders::Phong => "phong",
+            Shaders::Debug => "debug",
+        }
+    }
+
+    /// Vertex and fragment shader source built into the game
+    fn builtin_source(self) -> (&'static str, &'static str) {
+        match self {
+            Shaders::None => (include_str!("./shaders/nolighting.glslv"), include_str!("./shaders/nolighting.glslf")),
+            Shaders::Gouraud => (include_str!("./shaders/gouraud.glslv"), include_str!("./shaders/gouraud.glslf")),
+            Shaders::Phong => (include_str!("./shaders/phong.glslv"), include_str!("./shaders/phong.glslf")),
+            Shaders::Debug => (include_str!("./shaders/debug.glslv"), include_str!("./shaders/debug.glslf")),
+        }
+    }
+}
+
+/// How to draw blocks in a shading mode - which shader, which of its debug views, and whether to
+/// fill triangles or just outline them
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Shading {
+    pub shader: Shaders,
+    /// 0 for none, 1 for normals, 2 for chunk boundaries and 3 for chunk colours
+    pub debug_view: i32,
+    pub polygon_mode: glium::draw_parameters::PolygonMode,
+}
+
+impl Shading {
+    pub fn new(mode: ShadingMode) -> Shading {
+        let (shader, debug_view) = match mode {
+            ShadingMode::Phong => (Shaders::Phong, 0),
+            ShadingMode::Gouraud => (Shaders::Gouraud, 0),
+            ShadingMode::Unlit | ShadingMode::Wireframe => (Shaders::None, 0),
+            ShadingMode::Normals => (Shaders::Debug, 1),
+            ShadingMode::ChunkBoundaries => (Shaders::Debug, 2),
+            ShadingMode::ChunkColors => (Shaders::Debug, 3),
+        };
+        let polygon_mode = match mode {
+            ShadingMode::Wireframe => glium::draw_parameters::PolygonMode::Line,
+            _ => glium::draw_parameters::PolygonMode::Fill,
+        };
+        Shading { shader, debug_view, polygon_mode }
+    }
+}
+
+/// Compiles shaders, either those built into the game or ones read from a directory - which are
+/// watched for changes, so they can be edited while the game is running
+pub struct ShaderLoader {
+    directory: Option<PathBuf>,
+    /// When the files of each shader were last changed, as of when it was last loaded
+    modified: HashMap<Shaders, Option<SystemTime>>,
+    last_poll: Instant,
 }
 
-pub fn get_shader<F>(display: &F, shader: Shaders) -> glium::Program where F: glium::backend::Facade {
-    let program = match shader {
-        Shaders::None => glium::Program::from_source(
-            display,
-            include_str!("./shaders/nolighting.glslv"),
-            include_str!("./shaders/nolighting.glslf"),
-            None,
-        ),
-        Shaders::Gouraud => glium::Program::from_source(
-            display,
-            include_str!("./shaders/gouraud.glslv"),
-            include_str!("./shaders/gouraud.glslf"),
-            None,
-        ),
-        Shaders::Phong => glium::Program::from_source(
-            display,
-            include_str!("./shaders/phong.glslv"),
-            include_str!("./shaders/phong.glslf"),
-            None,
-        ),
-    };
-    program.unwrap()
+impl ShaderLoader {
+    pub fn new(directory: Option<PathBuf>) -> ShaderLoader {
+        ShaderLoader { directory, modified: HashMap::new(), last_poll: Instant::now() }
+    }
+
+    /// Compile `shader`, reporting any error from the compiler - shaders from a directory aren't
+    /// loaded again until they change, even if they failed to compile
+    pub fn load<F>(&mut self, display: &F, shader: Shaders) -> Result<glium::Program, String> where F: glium::backend::Facade {
+        let modified = self.modified_time(shader);
+        self.modified.insert(shader, modified);
+        let (vertex, fragment) = self.source(shader)?;
+        glium::Program::from_source(display, &vertex, &fragment, None)
+            .map_err(|e| format!("couldn't compile {} shader: {}", shader.name(), e))
+    }
+
+    /// Vertex and fragment shader source, read from the directory if there is one
+    fn source(&self, shader: Shaders) -> Result<(String, String), String> {
+        match self.directory {
+            Some(ref directory) => {
+                let read = |path: PathBuf| fs::read_to_string(&path).map_err(|e| format!("couldn't read {}: {}", path.display(), e));
+                Ok((read(self.path(directory, shader, "glslv"))?, read(self.path(directory, shader, "glslf"))?))
+            },
+            None => {
+                let (vertex, fragment) = shader.builtin_source();
+                Ok((vertex.to_owned(), fragment.to_owned()))
+            },
+        }
+    }
+
+    fn path(&self, directory: &Path, shader: Shaders, extension: &str) -> PathBuf {
+        directory.join(format!("{}.{}", shader.name(), extension))
+    }
+
+    /// When either of the shader's files was last changed, if it's read from a directory
+    fn modified_time(&self, shader: Shaders) -> Option<SystemTime> {
+        let directory = self.directory.as_ref()?;
+        ["glslv", "glslf"].iter()
+            .filter_map(|extension| fs::metadata(self.path(directory, shader, extension)).and_then(|metadata| metadata.modified()).ok())
+            .max()
+    }
+
+    /// Whether `shader` has changed since it was last loaded and should be loaded again - files
+    /// are only checked every SHADER_POLL_INTERVAL
+    pub fn changed(&mut self, shader: Shaders) -> bool {
+        if self.directory.is_none() || self.last_poll.elapsed() < SHADER_POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+        self.modified.get(&shader).is_some_and(|&modified| modified != self.modified_time(shader))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::time::{Instant, UNIX_EPOCH};
+    use glium::draw_parameters::PolygonMode;
+    use render::{Fog, Shading, ShaderLoader, Shaders, SHADER_POLL_INTERVAL};
+    use settings::{FogMode, FogSettings, ShadingMode};
+
+    #[test]
+    fn render_fog_follows_render_distance() {
+        let settings = FogSettings { mode: FogMode::Linear, start: 0.5, density: 4.0 };
+        assert_eq!(Fog::new(&settings, 2), Fog { mode: 1, start: 32.0, end: 64.0, density: 4.0 });
+        let off = FogSettings { mode: FogMode::Off, ..settings };
+        assert_eq!(Fog::new(&off, 4).mode, 0);
+    }
+
+    #[test]
+    fn render_shading_modes() {
+        assert_eq!(Shading::new(ShadingMode::Phong).shader, Shaders::Phong);
+        assert_eq!(Shading::new(ShadingMode::Wireframe).polygon_mode, PolygonMode::Line);
+        assert_eq!(Shading::new(ShadingMode::Unlit).polygon_mode, PolygonMode::Fill);
+        let chunk_colors = Shading::new(ShadingMode::ChunkColors);
+        assert_eq!((chunk_colors.shader, chunk_colors.debug_view), (Shaders::Debug, 3));
+        // every debug shader is built in
+        assert!(ShaderLoader::new(None).source(Shaders::Debug).is_ok());
+    }
+
+    #[test]
+    fn render_shader_directory() {
+        let directory = env::temp_dir().join(format!("ave-shaders-{}", ::std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("phong.glslv"), "vertex").unwrap();
+        fs::write(directory.join("phong.glslf"), "fragment").unwrap();
+        let mut loader = ShaderLoader::new(Some(directory.clone()));
+        assert_eq!(loader.source(Shaders::Phong), Ok(("vertex".to_owned(), "fragment".to_owned())));
+        assert!(loader.source(Shaders::Gouraud).is_err());
+
+        // as if loaded before the files were written
+        loader.modified.insert(Shaders::Phong, Some(UNIX_EPOCH));
+        loader.last_poll = Instant::now() - SHADER_POLL_INTERVAL;
+        assert!(loader.changed(Shaders::Phong));
+        // not checked again until the next poll
+        assert!(!loader.changed(Shaders::Phong));
+        fs::remove_dir_all(&directory).unwrap();
+
+        let builtin = ShaderLoader::new(None);
+        assert!(builtin.source(Shaders::Phong).unwrap().0.contains("#version"));
+    }
 }
@@ -0,0 +1,546 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use glutin::VirtualKeyCode;
+use simplelog::LogLevelFilter;
+use toml;
+use camera::MAX_MOVE_SPEED;
+use color::Color;
+use default;
+use worldgen::GeneratorParameters;
+
+/// Where settings are read from and written back to, relative to the working directory
+pub const DEFAULT_PATH: &str = "settings.toml";
+
+/// Largest accepted render distance (in chunks) - anything above this grinds to a halt
+const MAX_RENDER_DISTANCE: u8 = 16;
+
+/// User editable settings, loaded from a TOML file at startup
+///
+/// Any missing key falls back to its value in `default.rs`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub log_level: String,
+    pub window: WindowSettings,
+    pub render: RenderSettings,
+    pub camera: CameraSettings,
+    pub keys: KeyBindings,
+    pub world: WorldSettings,
+    /// File these settings were loaded from, and will be saved to
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowSettings {
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderSettings {
+    /// Radius (in chunks) around the camera to generate and render
+    pub distance: u8,
+    pub sky_color: Color,
+    pub fog: FogSettings,
+    pub shading: ShadingMode,
+    /// Directory to load shaders from instead of those built in, reloading them as they change
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shader_directory: Option<String>,
+}
+
+/// How blocks are drawn - lit in various ways, or one of the debug views
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShadingMode {
+    Phong,
+    Gouraud,
+    Unlit,
+    /// Outlines of every triangle, unlit
+    Wireframe,
+    /// Each face coloured by which way it faces
+    Normals,
+    /// Blocks on the edge of a chunk tinted red
+    ChunkBoundaries,
+    /// Each chunk tinted its own random colour
+    ChunkColors,
+}
+
+const SHADING_MODES: [ShadingMode; 7] = [
+    ShadingMode::Phong,
+    ShadingMode::Gouraud,
+    ShadingMode::Unlit,
+    ShadingMode::Wireframe,
+    ShadingMode::Normals,
+    ShadingMode::ChunkBoundaries,
+    ShadingMode::ChunkColors,
+];
+
+impl ShadingMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            ShadingMode::Phong => "phong",
+            ShadingMode::Gouraud => "gouraud",
+            ShadingMode::Unlit => "unlit",
+            ShadingMode::Wireframe => "wireframe",
+            ShadingMode::Normals => "normals",
+            ShadingMode::ChunkBoundaries => "chunk_boundaries",
+            ShadingMode::ChunkColors => "chunk_colors",
+        }
+    }
+
+    /// The mode after this one, going back to the first after the last
+    pub fn next(self) -> ShadingMode {
+        let index = SHADING_MODES.iter().position(|&mode| mode == self).unwrap();
+        SHADING_MODES[(index + 1) % SHADING_MODES.len()]
+    }
+}
+
+/// Fog hiding the edge of the rendered world, thickening with distance to the sky colour
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FogSettings {
+    pub mode: FogMode,
+    /// Where linear fog starts, as a fraction of the render distance - it's thickest at the
+    /// render distance
+    pub start: f32,
+    /// How quickly exponential fog thickens - at the render distance, all but e^-density of
+    /// the colour is fog
+    pub density: f32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FogMode {
+    Off,
+    Linear,
+    Exponential,
+}
+
+impl FogMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            FogMode::Off => "off",
+            FogMode::Linear => "linear",
+            FogMode::Exponential => "exponential",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<FogMode> {
+        [FogMode::Off, FogMode::Linear, FogMode::Exponential].iter().cloned().find(|mode| mode.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraSettings {
+    /// Vertical field of view (in degrees)
+    pub field_of_view: f32,
+    pub z_near: f32,
+    pub z_far: f32,
+    pub move_speed: f32,
+    pub rotation_speed: f32,
+}
+
+/// Which generator builds the world, and how
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorldSettings {
+    /// Name of a registered generator, e.g. `natural` or `flat`
+    pub generator: String,
+    /// A random seed is picked each run unless this is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<usize>,
+    /// Generator specific parameters, e.g. `sea_level = 4`
+    pub parameters: BTreeMap<String, toml::Value>,
+}
+
+/// Names of the keys bound to each control, as spelt in `glutin::VirtualKeyCode`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub move_forward: String,
+    pub move_backward: String,
+    pub move_left: String,
+    pub move_right: String,
+    pub move_up: String,
+    pub move_down: String,
+    pub rotate_up: String,
+    pub rotate_down: String,
+    pub rotate_left: String,
+    pub rotate_right: String,
+    pub speed_up: String,
+    pub slow_down: String,
+    pub toggle_cursor: String,
+    pub toggle_collision: String,
+    pub cycle_shading: String,
+}
+
+/// Something the user can do by pressing a key
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Control {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    RotateUp,
+    RotateDown,
+    RotateLeft,
+    RotateRight,
+    SpeedUp,
+    SlowDown,
+    ToggleCursor,
+    ToggleCollision,
+    CycleShading,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            log_level: default::LOG_LEVEL.to_owned(),
+            window: WindowSettings::default(),
+            render: RenderSettings::default(),
+            camera: CameraSettings::default(),
+            keys: KeyBindings::default(),
+            world: WorldSettings::default(),
+            path: PathBuf::from(DEFAULT_PATH),
+        }
+    }
+}
+
+impl Default for WindowSettings {
+    fn default() -> WindowSettings {
+        WindowSettings {
+            width: default::VIEWPORT_WIDTH,
+            height: default::VIEWPORT_HEIGHT,
+        }
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> RenderSettings {
+        RenderSettings {
+            distance: default::RENDER_DISTANCE,
+            sky_color: default::SKY_COLOR,
+            fog: FogSettings::default(),
+            shading: ShadingMode::Phong,
+            shader_directory: None,
+        }
+    }
+}
+
+impl Default for FogSettings {
+    fn default() -> FogSettings {
+        FogSettings {
+            mode: FogMode::Linear,
+            start: default::FOG_START,
+            density: default::FOG_DENSITY,
+        }
+    }
+}
+
+impl Default for CameraSettings {
+    fn default() -> CameraSettings {
+        CameraSettings {
+            field_of_view: default::FIELD_OF_VIEW,
+            z_near: default::Z_NEAR_CUTOFF,
+            z_far: default::Z_FAR_CUTOFF,
+            move_speed: default::MOVE_SPEED,
+            rotation_speed: default::ROTATION_SPEED,
+        }
+    }
+}
+
+impl Default for WorldSettings {
+    fn default() -> WorldSettings {
+        WorldSettings {
+            generator: default::WORLD_GENERATOR.to_owned(),
+            seed: None,
+            parameters: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for KeyBindings {
+    fn default() -> KeyBindings {
+        KeyBindings {
+            move_forward: "W".to_owned(),
+            move_backward: "S".to_owned(),
+            move_left: "A".to_owned(),
+            move_right: "D".to_owned(),
+            move_up: "Space".to_owned(),
+            move_down: "LControl".to_owned(),
+            rotate_up: "Up".to_owned(),
+            rotate_down: "Down".to_owned(),
+            rotate_left: "Left".to_owned(),
+            rotate_right: "Right".to_owned(),
+            speed_up: "Q".to_owned(),
+            slow_down: "E".to_owned(),
+            toggle_cursor: "Escape".to_owned(),
+            toggle_collision: "C".to_owned(),
+            cycle_shading: "F3".to_owned(),
+        }
+    }
+}
+
+impl Settings {
+    /// Load settings from `path`, using defaults if the file doesn't exist yet
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Settings, String> {
+        let path = path.as_ref();
+        let mut settings = match fs::read_to_string(path) {
+            Ok(contents) => Settings::parse(&contents)
+                .map_err(|e| format!("invalid settings in {}: {}", path.display(), e))?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Settings::default(),
+            Err(e) => return Err(format!("couldn't read {}: {}", path.display(), e)),
+        };
+        settings.path = path.to_path_buf();
+        Ok(settings)
+    }
+
+    /// Parse and validate settings from TOML
+    pub fn parse(contents: &str) -> Result<Settings, String> {
+        let settings: Settings = toml::from_str(contents).map_err(|e| e.to_string())?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Write settings back to the file they were loaded from
+    pub fn save(&self) -> Result<(), String> {
+        let contents = toml::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(&self.path, contents).map_err(|e| format!("couldn't write {}: {}", self.path.display(), e))
+    }
+
+    /// Check every setting, reporting all problems at once rather than just the first
+    pub fn validate(&self) -> Result<(), String> {
+        let mut errors = Vec::new();
+        if LogLevelFilter::from_str(&self.log_level).is_err() {
+            errors.push(format!("log_level: unknown level {:?}", self.log_level));
+        }
+        if self.window.width == 0 || self.window.height == 0 {
+            errors.push("window: width and height must be nonzero".to_owned());
+        }
+        if self.render.distance == 0 || self.render.distance > MAX_RENDER_DISTANCE {
+            errors.push(format!("render.distance: must be between 1 and {}", MAX_RENDER_DISTANCE));
+        }
+        if self.render.sky_color.iter().any(|c| !(0.0..=1.0).contains(c)) {
+            errors.push("render.sky_color: components must be between 0.0 and 1.0".to_owned());
+        }
+        if let Err(e) = self.render.fog.validate() {
+            errors.push(e);
+        }
+        if !(self.camera.field_of_view > 0.0 && self.camera.field_of_view < 180.0) {
+            errors.push("camera.field_of_view: must be between 0 and 180 degrees".to_owned());
+        }
+        if !(self.camera.z_near > 0.0 && self.camera.z_far > self.camera.z_near) {
+            errors.push("camera: z_near must be positive and less than z_far".to_owned());
+        }
+        if !(self.camera.move_speed > 0.0 && self.camera.rotation_speed > 0.0) {
+            errors.push("camera: move_speed and rotation_speed must be positive".to_owned());
+        }
+        if self.camera.move_speed > MAX_MOVE_SPEED {
+            errors.push(format!("camera.move_speed: must be at most {}", MAX_MOVE_SPEED));
+        }
+        if let Err(e) = self.keys.validate() {
+            errors.push(e);
+        }
+        if self.world.generator.is_empty() {
+            errors.push("world.generator: must name a generator".to_owned());
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; "))
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn log_level(&self) -> LogLevelFilter {
+        LogLevelFilter::from_str(&self.log_level).unwrap_or(LogLevelFilter::Info)
+    }
+}
+
+impl FogSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..1.0).contains(&self.start) {
+            return Err("render.fog.start: must be at least 0.0 and less than 1.0".to_owned());
+        }
+        if self.density <= 0.0 || self.density.is_nan() {
+            return Err("render.fog.density: must be positive".to_owned());
+        }
+        Ok(())
+    }
+}
+
+impl WorldSettings {
+    /// Parameters to hand to the generator - numbers and booleans are written out as strings
+    pub fn generator_parameters(&self) -> GeneratorParameters {
+        self.parameters.iter().map(|(name, value)| {
+            let value = match *value {
+                toml::Value::String(ref value) => value.clone(),
+                ref value => value.to_string(),
+            };
+            (name.clone(), value)
+        }).collect()
+    }
+}
+
+impl KeyBindings {
+    fn bindings(&self) -> Vec<(&str, &String, Control)> {
+        vec![
+            ("move_forward", &self.move_forward, Control::MoveForward),
+            ("move_backward", &self.move_backward, Control::MoveBackward),
+            ("move_left", &self.move_left, Control::MoveLeft),
+            ("move_right", &self.move_right, Control::MoveRight),
+            ("move_up", &self.move_up, Control::MoveUp),
+            ("move_down", &self.move_down, Control::MoveDown),
+            ("rotate_up", &self.rotate_up, Control::RotateUp),
+            ("rotate_down", &self.rotate_down, Control::RotateDown),
+            ("rotate_left", &self.rotate_left, Control::RotateLeft),
+            ("rotate_right", &self.rotate_right, Control::RotateRight),
+            ("speed_up", &self.speed_up, Control::SpeedUp),
+            ("slow_down", &self.slow_down, Control::SlowDown),
+            ("toggle_cursor", &self.toggle_cursor, Control::ToggleCursor),
+            ("toggle_collision", &self.toggle_collision, Control::ToggleCollision),
+            ("cycle_shading", &self.cycle_shading, Control::CycleShading),
+        ]
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let mut errors = Vec::new();
+        let mut seen: Vec<(&str, VirtualKeyCode)> = Vec::new();
+        for (name, key_name, _) in self.bindings() {
+            match key_from_name(key_name) {
+                None => errors.push(format!("keys.{}: unknown key {:?}", name, key_name)),
+                Some(key) => {
+                    if let Some(&(other, _)) = seen.iter().find(|&&(_, k)| k == key) {
+                        errors.push(format!("keys.{}: {:?} is already bound to {}", name, key_name, other));
+                    }
+                    seen.push((name, key));
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; "))
+        }
+    }
+
+    /// The control bound to a key, if any
+    pub fn control(&self, key: VirtualKeyCode) -> Option<Control> {
+        self.bindings().into_iter()
+            .find(|&(_, key_name, _)| key_from_name(key_name) == Some(key))
+            .map(|(_, _, control)| control)
+    }
+}
+
+macro_rules! key_names {
+    ($($key:ident),*) => {
+        /// Look up a key by its `VirtualKeyCode` variant name - only commonly bound keys are supported
+        pub fn key_from_name(name: &str) -> Option<VirtualKeyCode> {
+            match name {
+                $(stringify!($key) => Some(VirtualKeyCode::$key),)*
+                _ => None,
+            }
+        }
+    }
+}
+
+key_names!(
+    Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Escape, Insert, Home, Delete, End, PageDown, PageUp,
+    Left, Up, Right, Down,
+    Back, Return, Space, Tab,
+    Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    Add, Subtract, Multiply, Divide, Decimal, NumpadEnter,
+    Apostrophe, Backslash, Comma, Equals, Grave, LBracket, Minus, Period, RBracket, Semicolon, Slash,
+    LAlt, LControl, LShift, LWin, RAlt, RControl, RShift, RWin
+);
+
+#[cfg(test)]
+mod tests {
+    use glutin::VirtualKeyCode;
+    use settings::{Control, FogMode, Settings, ShadingMode};
+
+    #[test]
+    fn settings_defaults_are_valid() {
+        assert_eq!(Settings::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn settings_parse_partial() {
+        let settings = Settings::parse("[render]\ndistance = 4\n").unwrap();
+        assert_eq!(settings.render.distance, 4);
+        assert_eq!(settings.camera, Settings::default().camera);
+    }
+
+    #[test]
+    fn settings_roundtrip() {
+        let mut settings = Settings::default();
+        settings.camera.move_speed = 0.5;
+        settings.keys.speed_up = "PageUp".to_owned();
+        let contents = ::toml::to_string(&settings).unwrap();
+        assert_eq!(Settings::parse(&contents), Ok(settings));
+    }
+
+    #[test]
+    fn settings_validation_errors() {
+        assert!(Settings::parse("[render]\ndistance = 0\n").is_err());
+        assert!(Settings::parse("[camera]\nz_near = 10.0\nz_far = 1.0\n").is_err());
+        assert!(Settings::parse("log_level = \"loud\"\n").is_err());
+        assert!(Settings::parse("[camera]\nmove_speed = 5.0\n").is_err());
+        assert!(Settings::parse("[keys]\nmove_up = \"NotAKey\"\n").is_err());
+        assert!(Settings::parse("[keys]\nmove_up = \"W\"\n").is_err());
+        assert!(Settings::parse("[render.fog]\nstart = 1.0\n").is_err());
+        assert!(Settings::parse("[render.fog]\nmode = \"thick\"\n").is_err());
+    }
+
+    #[test]
+    fn settings_fog() {
+        let settings = Settings::parse("[render.fog]\nmode = \"exponential\"\ndensity = 2.0\n").unwrap();
+        assert_eq!(settings.render.fog.mode, FogMode::Exponential);
+        assert_eq!(settings.render.fog.start, Settings::default().render.fog.start);
+        assert_eq!(FogMode::from_name("off"), Some(FogMode::Off));
+    }
+
+    #[test]
+    fn settings_shading() {
+        let settings = Settings::parse("[render]\nshading = \"chunk_colors\"\n").unwrap();
+        assert_eq!(settings.render.shading, ShadingMode::ChunkColors);
+        assert_eq!(ShadingMode::ChunkColors.next(), ShadingMode::Phong);
+        assert_eq!(ShadingMode::Phong.next(), ShadingMode::Gouraud);
+        assert!(Settings::parse("[render]\nshading = \"cel\"\n").is_err());
+    }
+
+    #[test]
+    fn settings_world_parameters() {
+        let settings = Settings::parse("[world]\ngenerator = \"flat\"\nseed = 7\n[world.parameters]\npreset = \"3*dirt\"\nsea_level = 4\ncaves = false\n").unwrap();
+        assert_eq!(settings.world.generator, "flat");
+        assert_eq!(settings.world.seed, Some(7));
+        let parameters = settings.world.generator_parameters();
+        assert_eq!(parameters["preset"], "3*dirt");
+        assert_eq!(parameters["sea_level"], "4");
+        assert_eq!(parameters["caves"], "false");
+        assert!(Settings::parse("[world]\ngenerator = \"\"\n").is_err());
+    }
+
+    #[test]
+    fn settings_key_bindings() {
+        let settings = Settings::default();
+        assert_eq!(settings.keys.control(VirtualKeyCode::W), Some(Control::MoveForward));
+        assert_eq!(settings.keys.control(VirtualKeyCode::Escape), Some(Control::ToggleCursor));
+        assert_eq!(settings.keys.control(VirtualKeyCode::Z), None);
+    }
+}
@@ -0,0 +1,147 @@
+//! The shapes blocks can take, as lists of boxes for drawing and colliding with
+use block_state::{BlockState, Facing, Half};
+
+/// An axis aligned box within a block, from (0, 0, 0) to (1, 1, 1) for a whole cube
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Cuboid {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl Cuboid {
+    pub const fn new(min: [f32; 3], max: [f32; 3]) -> Cuboid {
+        Cuboid { min, max }
+    }
+
+    /// Whether a point within the block is inside this box
+    pub fn contains(&self, point: [f32; 3]) -> bool {
+        (0..3).all(|axis| point[axis] >= self.min[axis] && point[axis] < self.max[axis])
+    }
+}
+
+pub const FULL: Cuboid = Cuboid::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+const FENCE_POST: Cuboid = Cuboid::new([0.375, 0.0, 0.375], [0.625, 1.0, 0.625]);
+/// Fences are taller to collide with than they look, so they can't be stepped over
+const FENCE_POST_COLLISION: Cuboid = Cuboid::new([0.375, 0.0, 0.375], [0.625, 1.5, 0.625]);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Shape {
+    Cube,
+    /// Half a cube - the bottom or top half, as the block's `half` property says
+    Slab,
+    /// A slab with a step on it, rising toward the block's `facing` - upside down if its `half` is
+    /// the top
+    Stairs,
+    /// A thin post in the middle of the block
+    Fence,
+    /// Two crossed flat planes, e.g. for plants - drawn from both sides, and never collided with
+    Cross,
+    /// Any other arrangement of boxes
+    Boxes(&'static [Cuboid]),
+}
+
+impl Shape {
+    /// Parse a shape other than `Boxes` from its name, e.g. `slab`
+    pub fn from_name(name: &str) -> Option<Shape> {
+        match name {
+            "cube" => Some(Shape::Cube),
+            "slab" => Some(Shape::Slab),
+            "stairs" => Some(Shape::Stairs),
+            "fence" => Some(Shape::Fence),
+            "cross" => Some(Shape::Cross),
+            _ => None,
+        }
+    }
+
+    /// Whether the shape fills the whole block, so it can hide its neighbours' faces
+    pub fn is_full(&self) -> bool {
+        *self == Shape::Cube
+    }
+
+    /// The boxes drawn for a block in `state` - `Cross` is drawn as planes instead, so has none
+    pub fn boxes(&self, state: BlockState) -> Vec<Cuboid> {
+        let bottom = state.half() != Some(Half::Top);
+        match *self {
+            Shape::Cube => vec![FULL],
+            Shape::Slab => vec![if bottom { lower_half() } else { upper_half() }],
+            Shape::Stairs => {
+                let (base, step) = if bottom { (lower_half(), upper_half()) } else { (upper_half(), lower_half()) };
+                vec![base, step_toward(step, state.facing().unwrap_or(Facing::North))]
+            },
+            Shape::Fence => vec![FENCE_POST],
+            Shape::Cross => Vec::new(),
+            Shape::Boxes(boxes) => boxes.to_vec(),
+        }
+    }
+
+    /// The boxes a block in `state` can't be moved through
+    pub fn collision_boxes(&self, state: BlockState) -> Vec<Cuboid> {
+        match *self {
+            Shape::Fence => vec![FENCE_POST_COLLISION],
+            _ => self.boxes(state),
+        }
+    }
+}
+
+fn lower_half() -> Cuboid {
+    Cuboid::new([0.0, 0.0, 0.0], [1.0, 0.5, 1.0])
+}
+
+fn upper_half() -> Cuboid {
+    Cuboid::new([0.0, 0.5, 0.0], [1.0, 1.0, 1.0])
+}
+
+/// The half of `layer` toward `facing`
+fn step_toward(layer: Cuboid, facing: Facing) -> Cuboid {
+    let (mut min, mut max) = (layer.min, layer.max);
+    match facing {
+        Facing::North => max[2] = 0.5,
+        Facing::South => min[2] = 0.5,
+        Facing::East => min[0] = 0.5,
+        Facing::West => max[0] = 0.5,
+        // stairs can't face up or down
+        Facing::Up | Facing::Down => {},
+    }
+    Cuboid::new(min, max)
+}
+
+#[cfg(test)]
+mod tests {
+    use block;
+    use block::BlockType;
+    use block_state::{BlockState, Property, Value, Facing, Half};
+    use shape::{Cuboid, Shape};
+
+    static STAIRS: &BlockType = &BlockType {
+        properties: &[Property::Facing, Property::Half],
+        shape: Shape::Stairs,
+        ..*block::STONE
+    };
+
+    #[test]
+    fn shape_boxes() {
+        let stone = BlockState::new(block::STONE);
+        assert_eq!(Shape::Cube.boxes(stone), vec![Cuboid::new([0.0; 3], [1.0; 3])]);
+        assert!(Shape::Cube.is_full() && !Shape::Slab.is_full());
+        // without a half property, slabs sit at the bottom
+        assert_eq!(Shape::Slab.boxes(stone)[0].max[1], 0.5);
+        assert!(Shape::Cross.collision_boxes(stone).is_empty());
+        assert!(Shape::Fence.collision_boxes(stone)[0].contains([0.5, 1.2, 0.5]));
+        assert!(!Shape::Fence.boxes(stone)[0].contains([0.5, 1.2, 0.5]));
+    }
+
+    #[test]
+    fn shape_stairs_follow_state() {
+        let stairs = BlockState::new(STAIRS).with(Value::Facing(Facing::East)).unwrap();
+        let boxes = Shape::Stairs.collision_boxes(stairs);
+        let solid = |point| boxes.iter().any(|cuboid: &Cuboid| cuboid.contains(point));
+        assert!(solid([0.2, 0.2, 0.5]) && solid([0.8, 0.8, 0.5]));
+        assert!(!solid([0.2, 0.8, 0.5]));
+
+        let upside_down = stairs.with(Value::Half(Half::Top)).unwrap();
+        let boxes = Shape::Stairs.collision_boxes(upside_down);
+        let solid = |point| boxes.iter().any(|cuboid: &Cuboid| cuboid.contains(point));
+        assert!(solid([0.2, 0.8, 0.5]) && solid([0.8, 0.2, 0.5]));
+        assert!(!solid([0.2, 0.2, 0.5]));
+    }
+}
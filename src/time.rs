@@ -0,0 +1,195 @@
+//! Time of day in the world, and how the sun, sky and daylight follow it
+use std::f32::consts::PI;
+use cgmath::InnerSpace;
+use color;
+use color::Color;
+use space::Direction;
+
+/// Length of a whole day and night, in ticks - at 60 ticks a second, 20 minutes
+pub const DAY_LENGTH: u64 = 72000;
+
+/// Times of day, in ticks since the start of the day - the day starts at sunrise
+pub const DAWN: u64 = 0;
+pub const NOON: u64 = DAY_LENGTH / 4;
+pub const DUSK: u64 = DAY_LENGTH / 2;
+pub const MIDNIGHT: u64 = DAY_LENGTH * 3 / 4;
+
+/// How much of the sky light is left at night
+const NIGHT_DAYLIGHT: f32 = 0.2;
+
+const SUNRISE_COLOR: Color = [1.0, 0.6, 0.3];
+const NOON_COLOR: Color = [1.0, 1.0, 0.95];
+const MOONLIGHT_COLOR: Color = [0.25, 0.3, 0.45];
+
+const DAWN_SKY: Color = [0.98, 0.6, 0.45];
+const DUSK_SKY: Color = [0.9, 0.45, 0.3];
+const NIGHT_SKY: Color = [0.02, 0.03, 0.08];
+
+/// Sun and moon paths lean this far to the south, so they're never exactly overhead
+const TILT: f32 = 0.25;
+
+/// Time in the world, advanced every tick unless frozen
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct WorldTime {
+    /// Ticks since the world was created
+    ticks: u64,
+    frozen: bool,
+}
+
+impl WorldTime {
+    /// Time starting at `time_of_day`
+    pub fn new(time_of_day: u64) -> WorldTime {
+        WorldTime { ticks: time_of_day % DAY_LENGTH, frozen: false }
+    }
+
+    pub fn tick(&mut self) {
+        if !self.frozen {
+            self.ticks += 1;
+        }
+    }
+
+    /// Ticks since the start of the current day
+    pub fn time_of_day(&self) -> u64 {
+        self.ticks % DAY_LENGTH
+    }
+
+    /// Jump to `time_of_day` - forwards, so the day count never goes back
+    pub fn set_time_of_day(&mut self, time_of_day: u64) {
+        let day = self.ticks / DAY_LENGTH;
+        let target = day * DAY_LENGTH + time_of_day % DAY_LENGTH;
+        self.ticks = if target < self.ticks { target + DAY_LENGTH } else { target };
+    }
+
+    /// Move time forward - only the time of day changes, so adding whole days does nothing and
+    /// any number of ticks can be added without overflowing
+    pub fn add(&mut self, ticks: u64) {
+        self.ticks += ticks % DAY_LENGTH;
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    pub fn set_frozen(&mut self, frozen: bool) {
+        self.frozen = frozen;
+    }
+
+    /// How far through the day it is, from 0 at sunrise to 1 at the next
+    fn fraction(&self) -> f32 {
+        self.time_of_day() as f32 / DAY_LENGTH as f32
+    }
+
+    /// Direction toward the sun - rising in the east, overhead at noon and setting in the west
+    fn sun_direction(&self) -> Direction {
+        let angle = self.fraction() * 2.0 * PI;
+        Direction::new(angle.cos(), angle.sin(), TILT).normalize()
+    }
+
+    /// Direction toward whichever of the sun or moon is up, and the colour of its light - which
+    /// fades to nothing as either reaches the horizon, so lighting doesn't jump as they swap
+    pub fn sun(&self) -> (Direction, Color) {
+        let sun = self.sun_direction();
+        if sun.y >= 0.0 {
+            let color = color::lerp(SUNRISE_COLOR, NOON_COLOR, (sun.y / 0.5).min(1.0));
+            (sun, color::scale(color, smoothstep(0.0, 0.1, sun.y)))
+        } else {
+            let moon = Direction::new(-sun.x, -sun.y, sun.z);
+            (moon, color::scale(MOONLIGHT_COLOR, smoothstep(0.0, 0.1, moon.y)))
+        }
+    }
+
+    /// How much sky light there is, from NIGHT_DAYLIGHT at night to 1 in the day
+    pub fn daylight(&self) -> f32 {
+        let height = self.sun_direction().y;
+        NIGHT_DAYLIGHT + (1.0 - NIGHT_DAYLIGHT) * smoothstep(-0.2, 0.2, height)
+    }
+
+    /// Colour of the sky - `day_sky` during the day, through dawn and dusk colours to night
+    pub fn sky_color(&self, day_sky: Color) -> Color {
+        let keyframes = [
+            (0.0, DAWN_SKY),
+            (0.05, day_sky),
+            (0.45, day_sky),
+            (0.5, DUSK_SKY),
+            (0.55, NIGHT_SKY),
+            (0.95, NIGHT_SKY),
+            (1.0, DAWN_SKY),
+        ];
+        let fraction = self.fraction();
+        for pair in keyframes.windows(2) {
+            let ((start, from), (end, to)) = (pair[0], pair[1]);
+            if fraction <= end {
+                return color::lerp(from, to, (fraction - start) / (end - start));
+            }
+        }
+        DAWN_SKY
+    }
+}
+
+/// Parse a time of day, either in ticks or one of `dawn`, `noon`, `dusk` or `midnight`
+pub fn parse_time_of_day(time: &str) -> Result<u64, String> {
+    match time {
+        "dawn" => Ok(DAWN),
+        "noon" => Ok(NOON),
+        "dusk" => Ok(DUSK),
+        "midnight" => Ok(MIDNIGHT),
+        _ => time.parse::<u64>().map(|ticks| ticks % DAY_LENGTH)
+            .map_err(|_| format!("invalid time {:?} - expected ticks, dawn, noon, dusk or midnight", time)),
+    }
+}
+
+/// 0 below `low`, 1 above `high`, and smoothly between
+fn smoothstep(low: f32, high: f32, x: f32) -> f32 {
+    let t = ((x - low) / (high - low)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use color;
+    use time::{WorldTime, parse_time_of_day, DAY_LENGTH, DAWN, NOON, DUSK, MIDNIGHT};
+
+    #[test]
+    fn time_advances_unless_frozen() {
+        let mut time = WorldTime::new(DAY_LENGTH - 1);
+        time.tick();
+        assert_eq!(time.time_of_day(), 0);
+        time.set_frozen(true);
+        time.tick();
+        assert_eq!(time.time_of_day(), 0);
+
+        time.set_time_of_day(NOON);
+        assert_eq!(time.time_of_day(), NOON);
+        time.set_time_of_day(DAWN);
+        assert_eq!(time.time_of_day(), DAWN);
+        time.add(DAY_LENGTH + 5);
+        assert_eq!(time.time_of_day(), 5);
+        time.add(u64::MAX);
+        assert_eq!(time.time_of_day(), (5 + u64::MAX % DAY_LENGTH) % DAY_LENGTH);
+
+        assert_eq!(parse_time_of_day("dusk"), Ok(DUSK));
+        assert_eq!(parse_time_of_day("100"), Ok(100));
+        assert!(parse_time_of_day("teatime").is_err());
+    }
+
+    #[test]
+    fn time_sun_and_sky() {
+        let noon = WorldTime::new(NOON);
+        let (direction, color) = noon.sun();
+        assert!(direction.y > 0.9 && color[0] > 0.9);
+        assert!((noon.daylight() - 1.0).abs() < 1e-6);
+        assert_eq!(noon.sky_color(color::SKY), color::SKY);
+
+        let midnight = WorldTime::new(MIDNIGHT);
+        let (direction, color) = midnight.sun();
+        // the moon is up, and dimmer
+        assert!(direction.y > 0.9 && color[0] < 0.5);
+        assert!(midnight.daylight() < 0.5);
+        assert!(midnight.sky_color(color::SKY)[2] < 0.1);
+
+        // both fade out at the horizon
+        assert!(WorldTime::new(DAWN).sun().1.iter().all(|&c| c < 0.01));
+        let dusk = WorldTime::new(DUSK).sky_color(color::SKY);
+        assert!(dusk[0] > dusk[2]);
+    }
+}
@@ -0,0 +1,150 @@
+//! Headless command line tools, run as `ave <tool> [--option value]...` instead of opening a window
+use std::collections::HashMap;
+use std::str::FromStr;
+use block_registry;
+use block_registry::BlockRegistry;
+use map::{Region, TopDownMap};
+use worldgen::{WorldGenerator, GeneratorRegistry, default_ores};
+
+const USAGE: &str = "\
+usage: ave [tool] [--option value]...
+
+tools:
+    ore-stats    count blocks of each ore type over a region of generated chunks
+                 --seed N         world seed (default 0)
+                 --radius N       horizontal radius of the region around the origin, in chunks
+                                  (default 2)
+                 --min-x N, --max-x N, --min-z N, --max-z N
+                                  horizontal bounds of the region in chunk coordinates, instead
+                                  of those given by --radius
+                 --min-y N        lowest chunk y coordinate to sample (default -4)
+                 --max-y N        highest chunk y coordinate to sample (default 0)
+                 --generator NAME world generator to sample (default natural)
+    map          write a top down image of a region of generated chunks, coloured by the highest
+                 block of each column and shaded by height
+                 --output PATH    image to write (default map.png)
+                 --seed, --radius, --min-x, --max-x, --min-y, --max-y, --min-z, --max-z and
+                 --generator as for ore-stats
+
+any other --option is passed to the generator as a parameter, e.g. --sea_level 4";
+
+/// Run the tool named by the first command line argument, if any
+///
+/// Returns `None` if no tool was asked for, so the game should start as normal.
+pub fn run(args: &[String]) -> Option<Result<(), String>> {
+    let (tool, rest) = match args.split_first() {
+        Some((tool, rest)) => (tool, rest),
+        None => return None,
+    };
+    let result = parse_options(rest).and_then(|options| match tool.as_str() {
+        "ore-stats" => ore_stats(&options),
+        "map" => map(&options),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(())
+        },
+        _ => Err(format!("unknown tool {:?}\n{}", tool, USAGE)),
+    });
+    Some(result)
+}
+
+/// Parse `--name value` pairs
+fn parse_options(args: &[String]) -> Result<HashMap<String, String>, String> {
+    let mut options = HashMap::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            return Err(format!("unexpected argument {:?}\n{}", arg, USAGE));
+        }
+        match args.next() {
+            Some(value) => options.insert(arg[2..].to_owned(), value.clone()),
+            None => return Err(format!("missing value for {}", arg)),
+        };
+    }
+    Ok(options)
+}
+
+fn option<T: FromStr>(options: &HashMap<String, String>, name: &str, default: T) -> Result<T, String> {
+    match options.get(name) {
+        Some(value) => value.parse().map_err(|_| format!("invalid value {:?} for --{}", value, name)),
+        None => Ok(default),
+    }
+}
+
+/// Options every tool which samples a generator takes - anything else is a generator parameter
+const GENERATOR_OPTIONS: [&str; 9] = ["seed", "radius", "min-x", "max-x", "min-y", "max-y", "min-z", "max-z", "generator"];
+
+/// The region of chunks given by `--radius`, `--min-y` and `--max-y` - any of `--min-x`,
+/// `--max-x`, `--min-z` and `--max-z` replace the bounds given by the radius
+fn region(options: &HashMap<String, String>) -> Result<Region, String> {
+    let around = Region::around_origin(option(options, "radius", 2)?, option(options, "min-y", -4)?, option(options, "max-y", 0)?);
+    let region = Region {
+        min_x: option(options, "min-x", around.min_x)?,
+        max_x: option(options, "max-x", around.max_x)?,
+        min_z: option(options, "min-z", around.min_z)?,
+        max_z: option(options, "max-z", around.max_z)?,
+        ..around
+    };
+    if region.is_empty() {
+        return Err("region is empty".to_owned());
+    }
+    Ok(region)
+}
+
+/// The generator named by `--generator` (default natural) with seed `--seed`, given every option
+/// not in `GENERATOR_OPTIONS` or `tool_options` as a parameter
+fn generator(options: &HashMap<String, String>, tool_options: &[&str]) -> Result<Box<dyn WorldGenerator>, String> {
+    let name = generator_name(options);
+    let mut parameters = options.clone();
+    for tool_option in GENERATOR_OPTIONS.iter().chain(tool_options) {
+        parameters.remove(*tool_option);
+    }
+    let blocks = BlockRegistry::load(block_registry::DEFAULT_PATH)?;
+    GeneratorRegistry::with_builtins(blocks).create(name, option(options, "seed", 0)?, &parameters)
+}
+
+fn generator_name(options: &HashMap<String, String>) -> &str {
+    options.get("generator").map(String::as_str).unwrap_or("natural")
+}
+
+/// Print how many blocks of each ore (and of stone, for comparison) are generated in a region
+fn ore_stats(options: &HashMap<String, String>) -> Result<(), String> {
+    let region = region(options)?;
+    let mut generator = generator(options, &[])?;
+    let ores: Vec<_> = default_ores().iter().map(|ore| ore.block_type).collect();
+    let mut counts: HashMap<&str, u64> = HashMap::new();
+    let mut chunks = 0;
+    for x in region.min_x..region.max_x + 1 {
+        for y in region.min_y..region.max_y + 1 {
+            for z in region.min_z..region.max_z + 1 {
+                let chunk = generator.generate_chunk([x, y, z].into());
+                for block_type in chunk.blocks.values() {
+                    *counts.entry(block_type.name).or_insert(0) += 1;
+                }
+                chunks += 1;
+            }
+        }
+    }
+
+    let stone = *counts.get("stone").unwrap_or(&0);
+    let seed: usize = option(options, "seed", 0)?;
+    println!("{} generator, seed {}, {} chunks from x={} to x={}, y={} to y={} and z={} to z={}",
+        generator_name(options), seed, chunks, region.min_x, region.max_x, region.min_y, region.max_y, region.min_z, region.max_z);
+    println!("{:<12} {:>10} {:>10} {:>10}", "block", "count", "per chunk", "vs stone");
+    for name in ores.iter().map(|block_type| block_type.name).chain(Some("stone")) {
+        let count = *counts.get(name).unwrap_or(&0);
+        let ratio = if stone > 0 { 100.0 * count as f64 / stone as f64 } else { 0.0 };
+        println!("{:<12} {:>10} {:>10.1} {:>9.3}%", name, count, count as f64 / chunks as f64, ratio);
+    }
+    Ok(())
+}
+
+/// Write a top down map of a region to a PNG
+fn map(options: &HashMap<String, String>) -> Result<(), String> {
+    let region = region(options)?;
+    let mut generator = generator(options, &["output"])?;
+    let output = options.get("output").map(String::as_str).unwrap_or("map.png");
+    TopDownMap::generate(generator.as_mut(), region).save(output)?;
+    println!("wrote {}", output);
+    Ok(())
+}
@@ -1,11 +1,13 @@
 use space::Position;
 use std::collections::{HashMap, HashSet};
-use block::BlockType;
-use cgmath::Point3;
-use worldgen::WorldGenerator;
+use block::{BlockType, Mask};
+use block_state::BlockState;
+use block_registry::BlockRegistry;
+use cgmath::{Point3, Vector3};
+use worldgen::{WorldGenerator, GeneratorRegistry, GeneratorParameters};
 use std::vec::Vec;
 use space::Adjacent;
-use worldgen;
+use light::{BlockPosition, FaceLight, Light, LightMap};
 use rand;
 
 /// Side length of a chunk (in blocks) - all chunks are cubic
@@ -14,11 +16,15 @@ pub const CHUNK_SIZE: u8 = 32;
 /// Indicates an index into a chunk with dimensions CHUNK_SIZE x CHUNK_SIZE x CHUNK_SIZE
 pub type BlockCoordinates = Point3<u8>;
 
+#[derive(Debug, PartialEq)]
 pub struct HashChunk {
     /// Each chunk position is mapped to an index into the BLOCKS slice
     ///
     /// Absence of a chunk position key indicated an empty (air) block
     pub blocks: HashMap<BlockCoordinates, &'static BlockType>,
+    /// Packed properties of blocks which aren't in their default state - most blocks are, so most
+    /// positions need nothing more than their block type
+    pub states: HashMap<BlockCoordinates, u8>,
     /// Chunk positions which are completely occluded and so should never be rendered
     pub mask: HashSet<BlockCoordinates>,
 }
@@ -27,15 +33,29 @@ pub trait Chunk {
     /// get adjacent positions - ignoring diagonals
     fn get_adjacent(position: BlockCoordinates) -> HashSet<BlockCoordinates>;
     fn set(&mut self, position: BlockCoordinates, block_type: &'static BlockType);
+    /// replace the block at `position` with air
+    fn remove(&mut self, position: BlockCoordinates);
     fn get(&self, position: BlockCoordinates) -> Option<&&'static BlockType>;
+    /// set a block along with its properties
+    fn set_state(&mut self, position: BlockCoordinates, state: BlockState);
+    fn get_state(&self, position: BlockCoordinates) -> Option<BlockState>;
     fn is_occluded(&self, position: BlockCoordinates) -> bool;
     /// ideally this would be a lazy iterator - but need to think about lifetimes etc
-    fn get_visible(&self) -> HashSet<(BlockCoordinates, &BlockType)>;
+    fn get_visible(&self) -> HashSet<(BlockCoordinates, BlockState)>;
 }
 
 impl HashChunk {
     pub fn new() -> HashChunk {
-        HashChunk { blocks: HashMap::new(), mask: HashSet::new() }
+        HashChunk { blocks: HashMap::new(), states: HashMap::new(), mask: HashSet::new() }
+    }
+
+    /// Add `position` to the mask if it's occluded, and take it out if it isn't
+    fn update_mask(&mut self, position: BlockCoordinates) {
+        if self.is_occluded(position) {
+            self.mask.insert(position);
+        } else {
+            self.mask.remove(&position);
+        }
     }
 }
 
@@ -66,12 +86,21 @@ impl Chunk for HashChunk {
 
     fn set(&mut self, position: BlockCoordinates, block_type: &'static BlockType) {
         self.blocks.insert(position, block_type);
-        if self.is_occluded(position) {
-            self.mask.insert(position);
-        }
+        self.states.remove(&position);
+        // a replaced block may no longer hide its neighbours, e.g. stone replaced with water
+        self.update_mask(position);
         for adjacent_position in HashChunk::get_adjacent(position) {
-            if self.is_occluded(adjacent_position) {
-                self.mask.insert(adjacent_position);
+            self.update_mask(adjacent_position);
+        }
+    }
+
+    fn remove(&mut self, position: BlockCoordinates) {
+        if self.blocks.remove(&position).is_some() {
+            self.states.remove(&position);
+            // neighbours can now be seen through the gap
+            self.mask.remove(&position);
+            for adjacent_position in HashChunk::get_adjacent(position) {
+                self.mask.remove(&adjacent_position);
             }
         }
     }
@@ -80,25 +109,40 @@ impl Chunk for HashChunk {
         return self.blocks.get(&position);
     }
 
+    fn set_state(&mut self, position: BlockCoordinates, state: BlockState) {
+        self.set(position, state.block_type);
+        if !state.is_default() {
+            self.states.insert(position, state.bits());
+        }
+    }
+
+    fn get_state(&self, position: BlockCoordinates) -> Option<BlockState> {
+        self.blocks.get(&position).map(|&block_type| match self.states.get(&position) {
+            Some(&bits) => BlockState::from_bits(block_type, bits),
+            None => BlockState::new(block_type),
+        })
+    }
+
     fn is_occluded(&self, position: BlockCoordinates) -> bool {
         if [0, CHUNK_SIZE - 1].contains(&position[0]) || [0, CHUNK_SIZE - 1].contains(&position[1]) || [0, CHUNK_SIZE - 1].contains(&position[2]) {
             return false;  // cheating by for now always showing blocks that are on the edge of chunks
         }
+        let block_type = self.get(position).cloned();
         for adjacent_position in HashChunk::get_adjacent(position) {
             match self.get(adjacent_position) {
-                None => return false,
-                Some(_) => ()
+                Some(adjacent) if adjacent.hides(block_type) => (),
+                _ => return false,
             }
         }
         return true;
     }
 
     /// ideally this would be a lazy iterator - but need to think about lifetimes etc
-    fn get_visible(&self) -> HashSet<(BlockCoordinates, &BlockType)> {
+    fn get_visible(&self) -> HashSet<(BlockCoordinates, BlockState)> {
         let mut visible = HashSet::new();
-        for (chunk_position, block_type) in self.blocks.iter() {
+        for chunk_position in self.blocks.keys() {
             if !self.mask.contains(chunk_position) {
-                visible.insert((*chunk_position, *block_type));
+                visible.insert((*chunk_position, self.get_state(*chunk_position).unwrap()));
             };
         }
         return visible;
@@ -146,28 +190,145 @@ pub fn get_position(chunk_coordinates: &ChunkCoordinates, block_coordinates: &Bl
     return [x as f32, y as f32, z as f32].into();
 }
 
+/// The block containing `position`, in blocks
+pub fn block_position(position: Position) -> BlockPosition {
+    [position[0].floor() as i32, position[1].floor() as i32, position[2].floor() as i32].into()
+}
+
+/// The chunk a block is in, and where it is in that chunk
+pub fn split_block_position(position: BlockPosition) -> (ChunkCoordinates, BlockCoordinates) {
+    let size = CHUNK_SIZE as i32;
+    let chunk_coordinates = [position[0].div_euclid(size), position[1].div_euclid(size), position[2].div_euclid(size)].into();
+    let block_coordinates = [position[0].rem_euclid(size) as u8, position[1].rem_euclid(size) as u8, position[2].rem_euclid(size) as u8].into();
+    (chunk_coordinates, block_coordinates)
+}
+
 pub fn position_to_chunk(coordinates: &Position) -> ChunkCoordinates {
     ((coordinates[0] / CHUNK_SIZE as f32) as i32, (coordinates[1] / CHUNK_SIZE as f32) as i32, (coordinates[2] / CHUNK_SIZE as f32) as i32).into()
 }
 
 pub trait World {
+    #[allow(dead_code)]
     fn new() -> Self;
     fn get_or_create(&mut self, coordinates: ChunkCoordinates) -> &HashChunk;
-    fn at(&self, position: Position, radius: u8) -> Vec<(Position, &BlockType)>;
+    fn at(&self, position: Position, radius: u8) -> Vec<(Position, BlockState)>;
+}
+
+/// How a world's terrain is generated - enough to generate exactly the same world again
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorldMetadata {
+    /// Name of the generator in the `GeneratorRegistry`
+    pub generator: String,
+    pub seed: usize,
+    pub parameters: GeneratorParameters,
 }
 
 pub struct InMemoryWorld {
+    metadata: WorldMetadata,
     generator: Box<dyn WorldGenerator>,
     chunks: HashMap<ChunkCoordinates, HashChunk>,
+    light: LightMap,
+}
+
+impl InMemoryWorld {
+    /// A world whose generator is looked up by name in `registry`
+    pub fn with_metadata(metadata: WorldMetadata, registry: &GeneratorRegistry) -> Result<InMemoryWorld, String> {
+        let generator = registry.create(&metadata.generator, metadata.seed, &metadata.parameters)?;
+        Ok(InMemoryWorld { metadata, generator, chunks: HashMap::new(), light: LightMap::new() })
+    }
+
+    #[allow(dead_code)]
+    pub fn metadata(&self) -> &WorldMetadata {
+        &self.metadata
+    }
+
+    /// The block containing `position`, if there is one and its chunk has been generated
+    pub fn block_at(&self, position: Position) -> Option<&'static BlockType> {
+        self.state_at(position).map(|state| state.block_type)
+    }
+
+    /// The block containing `position`, with its properties
+    pub fn state_at(&self, position: Position) -> Option<BlockState> {
+        let (chunk_coordinates, block_coordinates) = split_block_position(block_position(position));
+        self.chunks.get(&chunk_coordinates).and_then(|chunk| chunk.get_state(block_coordinates))
+    }
+
+    /// Whether `position` is inside a block which can't be moved through
+    ///
+    /// Only the block's collision boxes count, so there's room above a slab - and as a fence's
+    /// reach up into the block above, the block below is checked too.
+    pub fn is_solid(&self, position: Position) -> bool {
+        [0.0, 1.0].iter().any(|&below| {
+            let base = Position::new(position[0].floor(), position[1].floor() - below, position[2].floor());
+            let point = [position[0] - base[0], position[1] - base[1], position[2] - base[2]];
+            self.state_at(base).is_some_and(|state| {
+                state.block_type.solid && state.block_type.shape.collision_boxes(state).iter().any(|cuboid| cuboid.contains(point))
+            })
+        })
+    }
+
+    /// Faces of the block at `position` hidden by the blocks next to it
+    pub fn hidden_faces(&self, position: Position, block_type: &BlockType) -> Mask {
+        let hidden = |x, y, z| self.block_at(position + Vector3::new(x, y, z)).is_some_and(|neighbour| neighbour.hides(Some(block_type)));
+        Mask {
+            up: hidden(0.0, 1.0, 0.0),
+            down: hidden(0.0, -1.0, 0.0),
+            north: hidden(0.0, 0.0, -1.0),
+            south: hidden(0.0, 0.0, 1.0),
+            east: hidden(1.0, 0.0, 0.0),
+            west: hidden(-1.0, 0.0, 0.0),
+        }
+    }
+
+    /// Light at `position` - chunks which haven't been generated are assumed to be open sky
+    pub fn light_at(&self, position: Position) -> Light {
+        self.light.get(block_position(position)).unwrap_or(Light::OPEN_SKY)
+    }
+
+    /// The light falling on each face of the block at `position`, from the space in front of it
+    pub fn face_light(&self, position: Position) -> FaceLight {
+        let light = |x, y, z| self.light_at(position + Vector3::new(x, y, z));
+        FaceLight {
+            up: light(0.0, 1.0, 0.0),
+            down: light(0.0, -1.0, 0.0),
+            north: light(0.0, 0.0, -1.0),
+            south: light(0.0, 0.0, 1.0),
+            east: light(1.0, 0.0, 0.0),
+            west: light(-1.0, 0.0, 0.0),
+            inside: light(0.0, 0.0, 0.0),
+        }
+    }
+
+    /// Place a block at `position`, relighting around it - does nothing if its chunk hasn't been
+    /// generated
+    #[allow(dead_code)]
+    pub fn set_state(&mut self, position: Position, state: BlockState) {
+        let (chunk_coordinates, block_coordinates) = split_block_position(block_position(position));
+        if let Some(chunk) = self.chunks.get_mut(&chunk_coordinates) {
+            chunk.set_state(block_coordinates, state);
+            self.light.block_changed(block_position(position), &self.chunks);
+        }
+    }
+
+    /// Replace the block at `position` with air, relighting around it
+    #[allow(dead_code)]
+    pub fn remove(&mut self, position: Position) {
+        let (chunk_coordinates, block_coordinates) = split_block_position(block_position(position));
+        if let Some(chunk) = self.chunks.get_mut(&chunk_coordinates) {
+            chunk.remove(block_coordinates);
+            self.light.block_changed(block_position(position), &self.chunks);
+        }
+    }
 }
 
 impl World for InMemoryWorld {
     fn new() -> InMemoryWorld {
-        let seed = rand::random::<usize>();
-        InMemoryWorld {
-            generator: Box::new(worldgen::NaturalWorldGenerator::new(seed)),
-            chunks: HashMap::new()
-        }
+        let metadata = WorldMetadata {
+            generator: "natural".to_owned(),
+            seed: rand::random::<usize>(),
+            parameters: GeneratorParameters::new(),
+        };
+        InMemoryWorld::with_metadata(metadata, &GeneratorRegistry::with_builtins(BlockRegistry::default())).unwrap()
     }
 
     fn get_or_create(&mut self, coordinates: ChunkCoordinates) -> &HashChunk {
@@ -176,11 +337,12 @@ impl World for InMemoryWorld {
         } else {
             let chunk = self.generator.generate_chunk(coordinates);
             self.chunks.insert(coordinates, chunk);
+            self.light.add_chunk(coordinates, &self.chunks);
             return self.chunks.get_mut(&coordinates).unwrap();
         }
     }
 
-    fn at(&self, position: Position, radius: u8) -> Vec<(Position, &BlockType)> {
+    fn at(&self, position: Position, radius: u8) -> Vec<(Position, BlockState)> {
         // for now, just return blocks of current nearby chunks
         let mut chunk_coordinates_to_render = HashSet::new();
         let current_chunk_coordinates = position_to_chunk(&position);
@@ -203,8 +365,8 @@ impl World for InMemoryWorld {
             let chunk_opt = self.chunks.get(&chunk_coordinates);
             match chunk_opt {
                 Some(chunk) => {
-                    for (block_coordinates, block_type) in chunk.get_visible() {
-                        blocks.push((get_position(&chunk_coordinates, &block_coordinates), block_type))
+                    for (block_coordinates, state) in chunk.get_visible() {
+                        blocks.push((get_position(&chunk_coordinates, &block_coordinates), state))
                     }
                 },
                 None => (),
@@ -217,7 +379,15 @@ impl World for InMemoryWorld {
 #[cfg(test)]
 mod tests {
     use block;
-    use world::{get_position, position_to_chunk, Chunk, HashChunk, CHUNK_SIZE};
+    use block_state::{BlockState, Value, Axis};
+    use block_registry::BlockRegistry;
+    use world::{get_position, position_to_chunk, Chunk, HashChunk, CHUNK_SIZE, World, InMemoryWorld, WorldMetadata};
+    use block::BlockType;
+    use shape::Shape;
+    use worldgen::{GeneratorRegistry, GeneratorParameters};
+
+    static SLAB: &BlockType = &BlockType { shape: Shape::Slab, ..*block::STONE };
+    static FENCE: &BlockType = &BlockType { shape: Shape::Fence, ..*block::WOOD };
 
     #[test]
     fn world_get_position() {
@@ -238,4 +408,105 @@ mod tests {
         chunk.set([0, 0, 0].into(), &block::GRASS);
         assert_eq!(chunk.get([0, 0, 0].into()), Some(&block::GRASS));
     }
+
+    #[test]
+    fn chunk_translucent_neighbours_dont_occlude() {
+        let mut chunk = HashChunk::new();
+        for &(x, y, z) in [(2, 1, 1), (0, 1, 1), (1, 2, 1), (1, 0, 1), (1, 1, 2), (1, 1, 0)].iter() {
+            chunk.set([x, y, z].into(), block::STONE);
+        }
+        chunk.set([1, 1, 1].into(), block::STONE);
+        assert!(chunk.mask.contains(&[1, 1, 1].into()));
+        // replacing a neighbour with water uncovers the stone
+        chunk.set([2, 1, 1].into(), block::WATER);
+        assert!(!chunk.mask.contains(&[1, 1, 1].into()));
+        assert!(chunk.get_visible().contains(&([1, 1, 1].into(), BlockState::new(block::STONE))));
+        // water is still hidden by the water next to it
+        chunk.set([1, 1, 1].into(), block::WATER);
+        assert!(chunk.mask.contains(&[1, 1, 1].into()));
+        assert_eq!(chunk.get_visible().len(), 6);
+    }
+
+    #[test]
+    fn chunk_remove_uncovers_neighbours() {
+        let mut chunk = HashChunk::new();
+        for x in 0..3 {
+            for y in 0..3 {
+                for z in 0..3 {
+                    chunk.set([x, y, z].into(), block::STONE);
+                }
+            }
+        }
+        assert!(chunk.mask.contains(&[1, 1, 1].into()));
+        chunk.remove([1, 2, 1].into());
+        assert_eq!(chunk.get([1, 2, 1].into()), None);
+        assert!(!chunk.mask.contains(&[1, 1, 1].into()));
+    }
+
+    #[test]
+    fn chunk_block_states() {
+        let mut chunk = HashChunk::new();
+        let log = BlockState::new(block::WOOD).with(Value::Axis(Axis::X)).unwrap();
+        chunk.set_state([1, 2, 3].into(), log);
+        chunk.set_state([1, 3, 3].into(), BlockState::new(block::WOOD));
+        assert_eq!(chunk.get_state([1, 2, 3].into()), Some(log));
+        assert_eq!(chunk.get([1, 2, 3].into()), Some(&block::WOOD));
+        assert_eq!(chunk.get_state([1, 3, 3].into()), Some(BlockState::new(block::WOOD)));
+        assert_eq!(chunk.get_state([1, 4, 3].into()), None);
+        // only blocks with properties away from their defaults take any more room
+        assert_eq!(chunk.states.len(), 1);
+        chunk.set([1, 2, 3].into(), block::WOOD);
+        assert_eq!(chunk.get_state([1, 2, 3].into()), Some(BlockState::new(block::WOOD)));
+        assert!(chunk.states.is_empty());
+    }
+
+    #[test]
+    fn world_from_metadata() {
+        let registry = GeneratorRegistry::with_builtins(BlockRegistry::default());
+        let parameters: GeneratorParameters = vec![("preset".to_owned(), "stone;1".to_owned())].into_iter().collect();
+        let metadata = WorldMetadata { generator: "flat".to_owned(), seed: 3, parameters };
+        let mut world = InMemoryWorld::with_metadata(metadata.clone(), &registry).unwrap();
+        assert_eq!(world.metadata(), &metadata);
+        assert_eq!(world.get_or_create([0, 0, 0].into()).get([0, 0, 0].into()), Some(&block::STONE));
+        let unknown = WorldMetadata { generator: "marble".to_owned(), ..metadata };
+        assert!(InMemoryWorld::with_metadata(unknown, &registry).is_err());
+    }
+
+    #[test]
+    fn world_solid_blocks() {
+        let parameters: GeneratorParameters = vec![("preset".to_owned(), "stone,water;0".to_owned())].into_iter().collect();
+        let metadata = WorldMetadata { generator: "flat".to_owned(), seed: 0, parameters };
+        let mut world = InMemoryWorld::with_metadata(metadata, &GeneratorRegistry::with_builtins(BlockRegistry::default())).unwrap();
+        world.get_or_create([-1, -1, -1].into());
+        assert_eq!(world.block_at([-0.5, -1.5, -31.9].into()), Some(block::STONE));
+        assert!(world.is_solid([-0.5, -1.5, -0.5].into()));
+        // water can be moved through, and so can chunks which haven't been generated yet
+        assert!(!world.is_solid([-0.5, -0.5, -0.5].into()));
+        assert!(!world.is_solid([0.5, -1.5, 0.5].into()));
+    }
+
+    #[test]
+    fn world_block_shapes() {
+        let parameters: GeneratorParameters = vec![("preset".to_owned(), "stone;0".to_owned())].into_iter().collect();
+        let metadata = WorldMetadata { generator: "flat".to_owned(), seed: 0, parameters };
+        let mut world = InMemoryWorld::with_metadata(metadata, &GeneratorRegistry::with_builtins(BlockRegistry::default())).unwrap();
+        world.get_or_create([0, -1, 0].into());
+        world.get_or_create([0, 0, 0].into());
+        let chunk = world.chunks.get_mut(&[0, 0, 0].into()).unwrap();
+        chunk.set([1, 0, 1].into(), SLAB);
+        chunk.set([2, 0, 1].into(), FENCE);
+
+        // there's room above a slab, and fences reach into the block above
+        assert!(world.is_solid([1.5, 0.2, 1.5].into()));
+        assert!(!world.is_solid([1.5, 0.8, 1.5].into()));
+        assert!(world.is_solid([2.5, 1.2, 1.5].into()));
+        assert!(!world.is_solid([2.1, 0.5, 1.5].into()));
+
+        // slabs hide nothing, but the stone under them hides their bottom
+        let slab = world.hidden_faces([1.0, 0.0, 1.0].into(), SLAB);
+        assert!(slab.down && !slab.north && !slab.up);
+        assert!(!world.hidden_faces([1.0, -1.0, 1.0].into(), block::STONE).up);
+        let stone = world.hidden_faces([5.0, -1.0, 5.0].into(), block::STONE);
+        assert!(stone.north && !stone.up);
+    }
 }
@@ -0,0 +1,256 @@
+use noise::{NoiseModule, Perlin, Seedable};
+use block;
+use block::BlockType;
+
+/// Broad kinds of terrain, chosen per column from temperature and humidity
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Biome {
+    Plains,
+    Desert,
+    Mountains,
+    Tundra,
+    Forest,
+    Ocean,
+}
+
+pub const BIOMES: [Biome; 6] = [
+    Biome::Plains,
+    Biome::Desert,
+    Biome::Mountains,
+    Biome::Tundra,
+    Biome::Forest,
+    Biome::Ocean,
+];
+
+/// Something small placed on top of the surface of some columns, e.g. cacti
+#[derive(Debug)]
+pub struct Decoration {
+    pub block_type: &'static BlockType,
+    /// Chance (from 0 to 1) of a column being decorated
+    pub chance: f32,
+    /// How many blocks tall the decoration is
+    pub height: i32,
+}
+
+#[derive(Debug)]
+#[allow(dead_code)]
+pub struct BiomeProperties {
+    pub name: &'static str,
+    /// Climate this biome is found in - both range from -1 to 1, though most noise is within ±0.5
+    pub temperature: f32,
+    pub humidity: f32,
+    /// Top block of each column
+    pub surface: &'static BlockType,
+    /// Blocks between the surface and the stone beneath
+    pub subsurface: &'static BlockType,
+    /// Multiplies the height of hills
+    pub height_scale: f32,
+    /// Raises (or lowers) the whole biome
+    pub height_offset: f32,
+    pub decorations: &'static [Decoration],
+    /// Chance (from 0 to 1) of a tree growing on a column
+    pub trees: f32,
+}
+
+static PLAINS: BiomeProperties = BiomeProperties {
+    name: "plains",
+    temperature: 0.1,
+    humidity: -0.05,
+    surface: block::GRASS,
+    subsurface: block::DIRT,
+    height_scale: 0.4,
+    height_offset: 2.0,
+    decorations: &[],
+    trees: 0.002,
+};
+
+static DESERT: BiomeProperties = BiomeProperties {
+    name: "desert",
+    temperature: 0.4,
+    humidity: -0.35,
+    surface: block::SAND,
+    subsurface: block::SAND,
+    height_scale: 0.3,
+    height_offset: 2.0,
+    decorations: &[Decoration { block_type: block::CACTUS, chance: 0.004, height: 3 }],
+    trees: 0.0,
+};
+
+static MOUNTAINS: BiomeProperties = BiomeProperties {
+    name: "mountains",
+    temperature: -0.2,
+    humidity: -0.35,
+    surface: block::STONE,
+    subsurface: block::STONE,
+    height_scale: 2.5,
+    height_offset: 12.0,
+    decorations: &[],
+    trees: 0.0,
+};
+
+static TUNDRA: BiomeProperties = BiomeProperties {
+    name: "tundra",
+    temperature: -0.45,
+    humidity: 0.05,
+    surface: block::SNOW,
+    subsurface: block::DIRT,
+    height_scale: 0.6,
+    height_offset: 4.0,
+    decorations: &[],
+    trees: 0.0,
+};
+
+static FOREST: BiomeProperties = BiomeProperties {
+    name: "forest",
+    temperature: 0.15,
+    humidity: 0.3,
+    surface: block::GRASS,
+    subsurface: block::DIRT,
+    height_scale: 0.8,
+    height_offset: 3.0,
+    decorations: &[],
+    trees: 0.03,
+};
+
+static OCEAN: BiomeProperties = BiomeProperties {
+    name: "ocean",
+    temperature: -0.05,
+    humidity: 0.5,
+    surface: block::SAND,
+    subsurface: block::SAND,
+    height_scale: 0.2,
+    height_offset: -20.0,
+    decorations: &[],
+    trees: 0.0,
+};
+
+impl Biome {
+    pub fn properties(&self) -> &'static BiomeProperties {
+        match *self {
+            Biome::Plains => &PLAINS,
+            Biome::Desert => &DESERT,
+            Biome::Mountains => &MOUNTAINS,
+            Biome::Tundra => &TUNDRA,
+            Biome::Forest => &FOREST,
+            Biome::Ocean => &OCEAN,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn name(&self) -> &'static str {
+        self.properties().name
+    }
+}
+
+/// Tunable parameters for `BiomeMap`
+#[derive(Debug, Clone, PartialEq)]
+pub struct BiomeParameters {
+    /// Horizontal frequency of the climate noise - lower values give bigger biomes
+    pub frequency: f32,
+    /// How far (in climate space) biomes blend into each other - higher values give smoother,
+    /// wider transitions
+    pub blend: f32,
+}
+
+impl Default for BiomeParameters {
+    fn default() -> BiomeParameters {
+        BiomeParameters {
+            frequency: 0.003,
+            blend: 0.2,
+        }
+    }
+}
+
+/// Biome of each column, driven by temperature and humidity noise
+pub struct BiomeMap {
+    temperature: Perlin,
+    humidity: Perlin,
+    parameters: BiomeParameters,
+}
+
+impl BiomeMap {
+    pub fn new(seed: usize, parameters: BiomeParameters) -> BiomeMap {
+        BiomeMap {
+            temperature: Perlin::new().set_seed(seed.wrapping_add(10)),
+            humidity: Perlin::new().set_seed(seed.wrapping_add(11)),
+            parameters,
+        }
+    }
+
+    /// (temperature, humidity) of the column at world (x, z)
+    pub fn climate(&self, x: f32, z: f32) -> (f32, f32) {
+        let f = self.parameters.frequency;
+        (self.temperature.get([x * f, z * f]), self.humidity.get([x * f, z * f]))
+    }
+
+    /// Squared distance in climate space from a column's climate to each biome
+    fn distances(&self, x: f32, z: f32) -> [f32; 6] {
+        let (temperature, humidity) = self.climate(x, z);
+        let mut distances = [0.0; 6];
+        for (distance, biome) in distances.iter_mut().zip(BIOMES.iter()) {
+            let properties = biome.properties();
+            *distance = (temperature - properties.temperature).powi(2) + (humidity - properties.humidity).powi(2);
+        }
+        distances
+    }
+
+    /// The biome whose climate is closest to that of the column at world (x, z)
+    pub fn biome_at(&self, x: f32, z: f32) -> Biome {
+        let distances = self.distances(x, z);
+        let mut nearest = 0;
+        for (i, distance) in distances.iter().enumerate() {
+            if *distance < distances[nearest] {
+                nearest = i;
+            }
+        }
+        BIOMES[nearest]
+    }
+
+    /// How much each biome (in the order of `BIOMES`) contributes to the column at world (x, z)
+    ///
+    /// Weights sum to 1, and change smoothly from column to column so biome borders don't
+    /// produce cliffs.
+    pub fn weights(&self, x: f32, z: f32) -> [f32; 6] {
+        let distances = self.distances(x, z);
+        let nearest = distances.iter().cloned().fold(f32::INFINITY, f32::min);
+        let blend = self.parameters.blend * self.parameters.blend;
+        let mut weights = [0.0; 6];
+        let mut total = 0.0;
+        for (weight, distance) in weights.iter_mut().zip(distances.iter()) {
+            // relative to the nearest biome, so this never underflows to all zeroes
+            *weight = (-(distance - nearest) / blend).exp();
+            total += *weight;
+        }
+        for weight in weights.iter_mut() {
+            *weight /= total;
+        }
+        weights
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use worldgen::biome::{BiomeMap, BiomeParameters};
+
+    #[test]
+    fn biome_weights_sum_to_one() {
+        let biomes = BiomeMap::new(42, BiomeParameters::default());
+        for &(x, z) in [(0.0, 0.0), (123.0, -456.0), (-1000.0, 2000.0)].iter() {
+            let total: f32 = biomes.weights(x, z).iter().sum();
+            assert!((total - 1.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn biome_at_varies_across_the_world() {
+        let biomes = BiomeMap::new(42, BiomeParameters::default());
+        let mut seen = HashSet::new();
+        for x in -20..20 {
+            for z in -20..20 {
+                seen.insert(biomes.biome_at(x as f32 * 100.0, z as f32 * 100.0));
+            }
+        }
+        assert!(seen.len() >= 4, "only found biomes {:?}", seen);
+    }
+}
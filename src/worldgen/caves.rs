@@ -0,0 +1,76 @@
+use noise::{NoiseModule, Perlin, Seedable};
+
+/// Tunable parameters for carving caves out of solid terrain
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaveParameters {
+    /// Frequency of the noise shaping large open caverns ("cheese" caves)
+    pub cavern_frequency: f32,
+    /// Noise value above which caverns are carved - lower values mean more and bigger caverns
+    pub cavern_threshold: f32,
+    /// Frequency of the noise shaping long winding tunnels ("spaghetti" caves)
+    pub tunnel_frequency: f32,
+    /// How close to zero both tunnel noises must be to carve - higher values mean wider tunnels
+    pub tunnel_width: f32,
+    /// Caves are only carved between these world heights
+    pub min_y: i32,
+    pub max_y: i32,
+    /// Caves never come closer than this many blocks to the surface
+    pub surface_margin: i32,
+}
+
+impl Default for CaveParameters {
+    fn default() -> CaveParameters {
+        CaveParameters {
+            cavern_frequency: 0.02,
+            cavern_threshold: 0.55,
+            tunnel_frequency: 0.03,
+            tunnel_width: 0.06,
+            min_y: -256,
+            max_y: 64,
+            surface_margin: 4,
+        }
+    }
+}
+
+/// Decides which blocks are carved out as caves
+///
+/// Everything is sampled in world coordinates, so caves line up across chunk borders no matter
+/// which chunk is generated first.
+pub struct Caves {
+    cavern: Perlin,
+    tunnel_a: Perlin,
+    tunnel_b: Perlin,
+    parameters: CaveParameters,
+}
+
+impl Caves {
+    pub fn new(seed: usize, parameters: CaveParameters) -> Caves {
+        Caves {
+            cavern: Perlin::new().set_seed(seed.wrapping_add(1)),
+            tunnel_a: Perlin::new().set_seed(seed.wrapping_add(2)),
+            tunnel_b: Perlin::new().set_seed(seed.wrapping_add(3)),
+            parameters,
+        }
+    }
+
+    /// Whether the block at world (x, y, z), in a column whose surface is at `surface_height`, is
+    /// carved out
+    pub fn is_cave(&self, x: i32, y: i32, z: i32, surface_height: i32) -> bool {
+        if y < self.parameters.min_y || y > self.parameters.max_y || y > surface_height - self.parameters.surface_margin {
+            return false;
+        }
+        let (x, y, z) = (x as f32, y as f32, z as f32);
+
+        let f = self.parameters.cavern_frequency;
+        // squash vertically so caverns are wider than they are tall
+        if self.cavern.get([x * f, y * f * 2.0, z * f]) > self.parameters.cavern_threshold {
+            return true;
+        }
+
+        // tunnels follow the curves where two independent noise fields both cross zero
+        let f = self.parameters.tunnel_frequency;
+        let width = self.parameters.tunnel_width;
+        self.tunnel_a.get([x * f, y * f, z * f]).abs() < width
+            && self.tunnel_b.get([x * f, y * f, z * f]).abs() < width
+    }
+}
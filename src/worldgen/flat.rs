@@ -0,0 +1,191 @@
+use world::{ChunkCoordinates, HashChunk, Chunk, CHUNK_SIZE};
+use worldgen::WorldGenerator;
+use worldgen::pipeline::{Pipeline, Stage, StageKind};
+use block::BlockType;
+use block_registry::BlockRegistry;
+
+/// Layers used by `FlatWorldGenerator::new` - 64 blocks of stone, then 3 of dirt, then grass
+pub const DEFAULT_PRESET: &str = "64*stone,3*dirt,grass";
+
+/// A horizontal layer of a flat world, `thickness` blocks deep
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layer {
+    pub block_type: &'static BlockType,
+    pub thickness: u32,
+}
+
+impl Layer {
+    /// Parse a layer like `3*dirt` (or `3x dirt`, `3×dirt`) - a missing count means a single block
+    pub fn parse(layer: &str, blocks: &BlockRegistry) -> Result<Layer, String> {
+        let layer = layer.trim();
+        let digits = layer.find(|c: char| !c.is_ascii_digit()).unwrap_or(layer.len());
+        let (thickness, name) = if digits == 0 {
+            (1, layer)
+        } else {
+            let rest = layer[digits..].trim_start();
+            // exactly one separator, so `3*xylem` is xylem rather than ylem
+            let name = match ['*', 'x', '×'].iter().find_map(|&separator| rest.strip_prefix(separator)) {
+                Some(name) => name,
+                None => return Err(format!("expected `*` after layer thickness in {:?}", layer)),
+            };
+            let thickness = layer[..digits].parse::<u32>()
+                .map_err(|e| format!("invalid layer thickness in {:?}: {}", layer, e))?;
+            (thickness, name.trim())
+        };
+        if thickness == 0 {
+            return Err(format!("layer {:?} has no thickness", layer));
+        }
+        match blocks.by_name(name) {
+            Some(block_type) => Ok(Layer { block_type, thickness }),
+            None => Err(format!("unknown block type {:?}", name)),
+        }
+    }
+}
+
+/// Horizontal layers stacked from the bottom up, with the top of the highest layer at
+/// `ground_height` - everything above is air, as is everything below the lowest layer
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlatLayers {
+    /// Layers from the bottom up
+    layers: Vec<Layer>,
+    /// World y coordinate of the first air block above the layers
+    ground_height: i32,
+}
+
+impl FlatLayers {
+    pub fn new(layers: Vec<Layer>, ground_height: i32) -> FlatLayers {
+        FlatLayers { layers, ground_height }
+    }
+
+    /// Block at world height `y` - the same for every column
+    pub fn block_at(&self, y: i32) -> Option<&'static BlockType> {
+        let mut top = self.ground_height as i64;
+        for layer in self.layers.iter().rev() {
+            let bottom = top - layer.thickness as i64;
+            if (y as i64) >= bottom && (y as i64) < top {
+                return Some(layer.block_type);
+            }
+            top = bottom;
+        }
+        None
+    }
+}
+
+impl Stage for FlatLayers {
+    fn name(&self) -> &'static str {
+        "layers"
+    }
+
+    fn kind(&self) -> StageKind {
+        StageKind::BaseTerrain
+    }
+
+    fn apply(&mut self, coordinates: ChunkCoordinates, chunk: &mut HashChunk) {
+        for y in 0..CHUNK_SIZE {
+            let world_y = coordinates[1] * CHUNK_SIZE as i32 + y as i32;
+            if let Some(block_type) = self.block_at(world_y) {
+                for x in 0..CHUNK_SIZE {
+                    for z in 0..CHUNK_SIZE {
+                        chunk.set([x, y, z].into(), block_type);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Generates a flat world with no structures
+///
+/// This is a pipeline of a single `layers` stage.
+pub struct FlatWorldGenerator {
+    layers: FlatLayers,
+    pipeline: Pipeline,
+}
+
+#[allow(dead_code)]
+impl FlatWorldGenerator {
+    pub fn new() -> FlatWorldGenerator {
+        FlatWorldGenerator::from_preset(DEFAULT_PRESET, &BlockRegistry::default()).unwrap()
+    }
+
+    pub fn with_layers(layers: Vec<Layer>, ground_height: i32) -> FlatWorldGenerator {
+        let layers = FlatLayers::new(layers, ground_height);
+        let pipeline = Pipeline::new().with_stage(layers.clone());
+        FlatWorldGenerator { layers, pipeline }
+    }
+
+    /// Parse a preset of comma separated layers from the bottom up, optionally followed by `;` and
+    /// a ground height (which defaults to 0) - layers can be any block in `blocks`
+    ///
+    /// e.g. `60*stone,3*dirt,grass;64` is a superflat world with grass at y=63
+    pub fn from_preset(preset: &str, blocks: &BlockRegistry) -> Result<FlatWorldGenerator, String> {
+        let mut parts = preset.splitn(2, ';');
+        let layers = parts.next().unwrap_or("");
+        let ground_height = match parts.next() {
+            Some(height) => height.trim().parse::<i32>()
+                .map_err(|e| format!("invalid ground height {:?}: {}", height, e))?,
+            None => 0,
+        };
+        if layers.trim().is_empty() {
+            return Err("preset has no layers".to_owned());
+        }
+        let layers = layers.split(',').map(|layer| Layer::parse(layer, blocks)).collect::<Result<Vec<_>, _>>()?;
+        Ok(FlatWorldGenerator::with_layers(layers, ground_height))
+    }
+
+    /// Block at world height `y` - the same for every column
+    pub fn block_at(&self, y: i32) -> Option<&'static BlockType> {
+        self.layers.block_at(y)
+    }
+
+    /// The stages this generator runs, to reorder or add to
+    pub fn pipeline_mut(&mut self) -> &mut Pipeline {
+        &mut self.pipeline
+    }
+}
+
+impl WorldGenerator for FlatWorldGenerator {
+    fn generate_chunk(&mut self, coordinates: ChunkCoordinates) -> HashChunk {
+        self.pipeline.generate_chunk(coordinates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use block;
+    use block_registry::BlockRegistry;
+    use worldgen::{FlatWorldGenerator, Layer};
+
+    #[test]
+    fn flat_layer_parse() {
+        let blocks = BlockRegistry::default();
+        assert_eq!(Layer::parse("3*dirt", &blocks), Ok(Layer { block_type: block::DIRT, thickness: 3 }));
+        assert_eq!(Layer::parse(" 60×stone ", &blocks), Ok(Layer { block_type: block::STONE, thickness: 60 }));
+        assert_eq!(Layer::parse("2x sand", &blocks), Ok(Layer { block_type: block::SAND, thickness: 2 }));
+        assert_eq!(Layer::parse("grass", &blocks), Ok(Layer { block_type: block::GRASS, thickness: 1 }));
+        assert!(Layer::parse("0*dirt", &blocks).is_err());
+        assert!(Layer::parse("3*bedrock", &blocks).is_err());
+        // only one separator is stripped
+        assert!(Layer::parse("3x*x*dirt", &blocks).is_err());
+        assert_eq!(Layer::parse("3*xylem", &blocks), Err("unknown block type \"xylem\"".to_owned()));
+    }
+
+    #[test]
+    fn flat_preset_layers_from_bottom_up() {
+        let generator = FlatWorldGenerator::from_preset("2*stone,3*dirt,grass;10", &BlockRegistry::default()).unwrap();
+        assert_eq!(generator.block_at(10), None);
+        assert_eq!(generator.block_at(9), Some(block::GRASS));
+        assert_eq!(generator.block_at(8), Some(block::DIRT));
+        assert_eq!(generator.block_at(6), Some(block::DIRT));
+        assert_eq!(generator.block_at(5), Some(block::STONE));
+        assert_eq!(generator.block_at(4), Some(block::STONE));
+        assert_eq!(generator.block_at(3), None);
+    }
+
+    #[test]
+    fn flat_preset_errors() {
+        assert!(FlatWorldGenerator::from_preset("", &BlockRegistry::default()).is_err());
+        assert!(FlatWorldGenerator::from_preset("3*dirt;high", &BlockRegistry::default()).is_err());
+        assert!(FlatWorldGenerator::from_preset("3*dirt,,grass", &BlockRegistry::default()).is_err());
+    }
+}
@@ -0,0 +1,279 @@
+use noise::{NoiseModule, Perlin, Seedable};
+
+/// Shifts each octave so octaves of the same noise don't all line up at the origin
+const OCTAVE_OFFSET: f32 = 71.3;
+
+/// Maps a noise value to a fraction of the terrain's amplitude, along a smooth curve through
+/// `(noise, height)` points
+///
+/// The curve is a monotone cubic spline (Fritsch-Carlson), so it has no kinks at the points and
+/// never overshoots them - between two points it only rises, only falls, or stays flat. Noise
+/// outside the first or last point is clamped to it. With no points, the noise is raised to the
+/// fourth power, giving mostly flat ground with the odd steep hill.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct HeightCurve {
+    /// Points sorted by noise value, which ranges from -1 to 1
+    points: Vec<(f32, f32)>,
+    /// Slope of the curve at each point
+    tangents: Vec<f32>,
+}
+
+impl HeightCurve {
+    /// A curve through `points`, which must be sorted by noise value with no two the same
+    pub fn new(points: Vec<(f32, f32)>) -> Result<HeightCurve, String> {
+        if points.iter().any(|&(x, y)| !x.is_finite() || !y.is_finite()) {
+            return Err(format!("height curve points must be finite numbers, not {:?}", points));
+        }
+        for pair in points.windows(2) {
+            if pair[1].0 <= pair[0].0 {
+                return Err(format!("height curve points must be sorted by noise value, with none repeated - {:?} then {:?}", pair[0], pair[1]));
+            }
+        }
+        let tangents = HeightCurve::tangents(&points);
+        Ok(HeightCurve { points, tangents })
+    }
+
+    /// Parse points like `-0.5:0,0:0.1,0.5:1` - each is `noise:height`
+    pub fn parse(points: &str) -> Result<HeightCurve, String> {
+        let points = points.split(',').map(|point| {
+            let mut parts = point.splitn(2, ':').map(|part| part.trim().parse::<f32>());
+            match (parts.next(), parts.next()) {
+                (Some(Ok(x)), Some(Ok(y))) => Ok((x, y)),
+                _ => Err(format!("invalid height curve point {:?} - expected noise:height", point)),
+            }
+        }).collect::<Result<Vec<_>, _>>()?;
+        HeightCurve::new(points)
+    }
+
+    /// Fritsch-Carlson tangents - the average of the slopes either side of each point, flattened
+    /// where the curve turns and scaled down wherever they'd make it overshoot
+    fn tangents(points: &[(f32, f32)]) -> Vec<f32> {
+        let slopes: Vec<f32> = points.windows(2).map(|pair| (pair[1].1 - pair[0].1) / (pair[1].0 - pair[0].0)).collect();
+        if slopes.is_empty() {
+            return vec![0.0; points.len()];
+        }
+        let mut tangents = Vec::with_capacity(points.len());
+        tangents.push(slopes[0]);
+        for pair in slopes.windows(2) {
+            tangents.push(if pair[0] * pair[1] <= 0.0 { 0.0 } else { (pair[0] + pair[1]) / 2.0 });
+        }
+        tangents.push(slopes[slopes.len() - 1]);
+        for (i, &slope) in slopes.iter().enumerate() {
+            if slope == 0.0 {
+                tangents[i] = 0.0;
+                tangents[i + 1] = 0.0;
+                continue;
+            }
+            let (a, b) = (tangents[i] / slope, tangents[i + 1] / slope);
+            let length = (a * a + b * b).sqrt();
+            if length > 3.0 {
+                tangents[i] = 3.0 / length * a * slope;
+                tangents[i + 1] = 3.0 / length * b * slope;
+            }
+        }
+        tangents
+    }
+
+    pub fn get(&self, noise: f32) -> f32 {
+        let points = &self.points;
+        if points.is_empty() {
+            return noise.powi(4);
+        }
+        if noise <= points[0].0 {
+            return points[0].1;
+        }
+        for (i, pair) in points.windows(2).enumerate() {
+            let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+            if noise <= x1 {
+                // cubic Hermite between the two points
+                let h = x1 - x0;
+                let t = (noise - x0) / h;
+                let (t2, t3) = (t * t, t * t * t);
+                return (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+                    + (t3 - 2.0 * t2 + t) * h * self.tangents[i]
+                    + (3.0 * t2 - 2.0 * t3) * y1
+                    + (t3 - t2) * h * self.tangents[i + 1];
+            }
+        }
+        points[points.len() - 1].1
+    }
+}
+
+/// Tunable parameters for the noise shaping the hills of `NaturalWorldGenerator`
+#[derive(Debug, Clone, PartialEq)]
+pub struct FractalParameters {
+    /// Horizontal frequency of the first octave - lower values give broader hills
+    pub frequency: f32,
+    /// Number of layers of noise summed together - more octaves give rougher, more detailed terrain
+    pub octaves: u32,
+    /// How much the frequency grows with each octave
+    pub lacunarity: f32,
+    /// How much the strength of each octave falls with each octave
+    pub persistence: f32,
+    /// How much ridged multifractal noise (sharp mountain ridges) is blended in, from 0 (smooth
+    /// hills only) to 1 (ridges only)
+    pub ridged: f32,
+    /// How far (in blocks) the noise is pushed around by domain warping - 0 turns warping off
+    pub warp_strength: f32,
+    /// Horizontal frequency of the noise doing the warping
+    pub warp_frequency: f32,
+    /// Maps the smooth hills' noise to height
+    pub height_curve: HeightCurve,
+}
+
+impl Default for FractalParameters {
+    fn default() -> FractalParameters {
+        FractalParameters {
+            frequency: 0.015,
+            octaves: 1,
+            lacunarity: 2.0,
+            persistence: 0.5,
+            ridged: 0.0,
+            warp_strength: 0.0,
+            warp_frequency: 0.004,
+            height_curve: HeightCurve::default(),
+        }
+    }
+}
+
+/// Fractal noise giving the height of the terrain, as a fraction of its amplitude
+pub struct FractalNoise {
+    hills: Perlin,
+    ridges: Perlin,
+    warp_x: Perlin,
+    warp_z: Perlin,
+    parameters: FractalParameters,
+}
+
+impl FractalNoise {
+    pub fn new(seed: usize, parameters: FractalParameters) -> FractalNoise {
+        FractalNoise {
+            hills: Perlin::new().set_seed(seed),
+            ridges: Perlin::new().set_seed(seed.wrapping_add(22)),
+            warp_x: Perlin::new().set_seed(seed.wrapping_add(20)),
+            warp_z: Perlin::new().set_seed(seed.wrapping_add(21)),
+            parameters,
+        }
+    }
+
+    /// Fractal Brownian motion - octaves of noise summed, each at a higher frequency and lower
+    /// strength than the last, normalised to between -1 and 1
+    pub fn fbm(&self, x: f32, z: f32) -> f32 {
+        let p = &self.parameters;
+        let (mut frequency, mut strength) = (p.frequency, 1.0);
+        let (mut total, mut max) = (0.0, 0.0);
+        for octave in 0..p.octaves.max(1) {
+            let offset = octave as f32 * OCTAVE_OFFSET;
+            total += strength * self.hills.get([x * frequency + offset, z * frequency + offset]);
+            max += strength;
+            frequency *= p.lacunarity;
+            strength *= p.persistence;
+        }
+        total / max
+    }
+
+    /// Ridged multifractal noise - sharp crests where the noise crosses zero, with detail piling up
+    /// along the ridges - from 0 to 1
+    pub fn ridged(&self, x: f32, z: f32) -> f32 {
+        let p = &self.parameters;
+        let (mut frequency, mut strength, mut weight) = (p.frequency, 1.0, 1.0);
+        let (mut total, mut max) = (0.0, 0.0);
+        for octave in 0..p.octaves.max(1) {
+            let offset = octave as f32 * OCTAVE_OFFSET;
+            let signal = 1.0 - self.ridges.get([x * frequency + offset, z * frequency + offset]).abs();
+            let signal = signal * signal * weight;
+            // later octaves only add detail where earlier ones found a ridge
+            weight = signal.clamp(0.0, 1.0);
+            total += strength * signal;
+            max += strength;
+            frequency *= p.lacunarity;
+            strength *= p.persistence;
+        }
+        total / max
+    }
+
+    /// Height of the column at world (x, z), as a fraction of the amplitude
+    pub fn get(&self, x: f32, z: f32) -> f32 {
+        let p = &self.parameters;
+        let (x, z) = if p.warp_strength != 0.0 {
+            let f = p.warp_frequency;
+            (x + p.warp_strength * self.warp_x.get([x * f, z * f]), z + p.warp_strength * self.warp_z.get([x * f, z * f]))
+        } else {
+            (x, z)
+        };
+        let hills = p.height_curve.get(self.fbm(x, z));
+        if p.ridged > 0.0 {
+            hills * (1.0 - p.ridged) + self.ridged(x, z) * p.ridged
+        } else {
+            hills
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noise::{NoiseModule, Perlin, Seedable};
+    use worldgen::fractal::{FractalNoise, FractalParameters, HeightCurve};
+
+    #[test]
+    fn fractal_single_octave_is_plain_perlin() {
+        let noise = FractalNoise::new(42, FractalParameters::default());
+        let perlin = Perlin::new().set_seed(42);
+        for &(x, z) in [(0.0, 0.0), (10.5, -3.0), (-700.0, 1234.0)].iter() {
+            let expected = perlin.get([x * 0.015, z * 0.015]);
+            assert_eq!(noise.fbm(x, z), expected);
+            assert_eq!(noise.get(x, z), expected.powi(4));
+        }
+    }
+
+    #[test]
+    fn fractal_octaves_ridges_and_warping_stay_in_range() {
+        let parameters = FractalParameters { octaves: 6, ridged: 0.5, warp_strength: 40.0, ..FractalParameters::default() };
+        let noise = FractalNoise::new(7, parameters);
+        let smooth = FractalNoise::new(7, FractalParameters::default());
+        let mut differs = false;
+        for x in -50..50 {
+            for z in -50..50 {
+                let (x, z) = (x as f32 * 13.0, z as f32 * 13.0);
+                assert!(noise.fbm(x, z).abs() <= 1.0);
+                let ridged = noise.ridged(x, z);
+                assert!((0.0..=1.0).contains(&ridged), "ridged noise {} out of range", ridged);
+                let height = noise.get(x, z);
+                assert!((0.0..=1.0).contains(&height), "height {} out of range", height);
+                differs |= height != smooth.get(x, z);
+            }
+        }
+        assert!(differs);
+    }
+
+    #[test]
+    fn fractal_height_curve() {
+        let curve = HeightCurve::new(vec![(-0.5, 0.0), (0.0, 0.1), (0.5, 1.0)]).unwrap();
+        assert_eq!(curve, HeightCurve::parse("-0.5:0, 0:0.1, 0.5:1").unwrap());
+        assert_eq!(curve.get(-1.0), 0.0);
+        assert_eq!(curve.get(-0.5), 0.0);
+        assert!((curve.get(0.0) - 0.1).abs() < 1e-6);
+        assert_eq!(curve.get(0.5), 1.0);
+        assert_eq!(curve.get(1.0), 1.0);
+        assert_eq!(HeightCurve::default().get(-0.5), 0.0625);
+
+        // rises steadily without overshooting the points, and has no kink at the middle one
+        let mut previous = 0.0;
+        for i in -50..51 {
+            let height = curve.get(i as f32 / 100.0);
+            assert!(height >= previous && height <= 1.0, "height {} at {}", height, i);
+            previous = height;
+        }
+        let step = 1e-3;
+        let left = (curve.get(0.0) - curve.get(-step)) / step;
+        let right = (curve.get(step) - curve.get(0.0)) / step;
+        assert!((left - right).abs() < 0.05, "slope {} then {}", left, right);
+        // flat stretches stay flat
+        let plateau = HeightCurve::new(vec![(-1.0, 0.0), (0.0, 0.5), (0.5, 0.5), (1.0, 1.0)]).unwrap();
+        assert_eq!(plateau.get(0.25), 0.5);
+
+        assert!(HeightCurve::new(vec![(0.5, 1.0), (-0.5, 0.0)]).is_err());
+        assert!(HeightCurve::new(vec![(0.0, 0.0), (0.0, 1.0)]).is_err());
+        assert!(HeightCurve::parse("0:0,1").is_err());
+    }
+}
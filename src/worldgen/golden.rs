@@ -0,0 +1,97 @@
+//! Golden-hash regression tests for world generators
+//!
+//! Each generator is run over a fixed grid of chunks for a few seeds, and the contents hashed
+//! into a digest which is compared against `goldens.txt`. If terrain is changed on purpose (e.g.
+//! tweaking noise parameters or upgrading the `noise` crate), bless the new digests with
+//!
+//!     BLESS=1 cargo test worldgen_goldens
+//!
+//! and commit the updated `goldens.txt` alongside the change.
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use world::{ChunkCoordinates, HashChunk};
+use block_registry::BlockRegistry;
+use worldgen::{StableHasher, WorldGenerator, GeneratorRegistry, GeneratorParameters};
+
+const SEEDS: [usize; 3] = [0, 42, 1234];
+
+/// Horizontal chunk coordinates of the grid
+const GRID_XZ: [i32; 2] = [-1, 0];
+/// Vertical chunk coordinates of the grid - underground, ground level and sky
+const GRID_Y: [i32; 3] = [-1, 0, 1];
+
+fn goldens_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/worldgen/goldens.txt")
+}
+
+fn generators(seed: usize) -> Vec<(&'static str, Box<dyn WorldGenerator>)> {
+    let registry = GeneratorRegistry::with_builtins(BlockRegistry::default());
+    ["flat", "pillars", "natural"].iter()
+        .map(|&name| (name, registry.create(name, seed, &GeneratorParameters::new()).unwrap()))
+        .collect()
+}
+
+/// Hash the contents of a chunk, visiting blocks in a fixed order
+fn hash_chunk(hasher: &mut StableHasher, coordinates: ChunkCoordinates, chunk: &HashChunk) {
+    hasher.write_i32(coordinates[0]);
+    hasher.write_i32(coordinates[1]);
+    hasher.write_i32(coordinates[2]);
+    let mut blocks: Vec<_> = chunk.blocks.iter().collect();
+    blocks.sort_by_key(|&(position, _)| (position[0], position[1], position[2]));
+    for (position, block_type) in blocks {
+        hasher.write(&[position[0], position[1], position[2]]);
+        hasher.write(block_type.name.as_bytes());
+    }
+}
+
+fn digest(generator: &mut dyn WorldGenerator) -> u64 {
+    let mut hasher = StableHasher::new();
+    for &x in GRID_XZ.iter() {
+        for &y in GRID_Y.iter() {
+            for &z in GRID_XZ.iter() {
+                let coordinates = [x, y, z].into();
+                let chunk = generator.generate_chunk(coordinates);
+                hash_chunk(&mut hasher, coordinates, &chunk);
+            }
+        }
+    }
+    hasher.finish()
+}
+
+/// Lines of `<generator> <seed> <digest>`, in a stable order
+fn compute_goldens() -> Vec<String> {
+    let mut lines = Vec::new();
+    for seed in SEEDS.iter() {
+        for (name, mut generator) in generators(*seed) {
+            lines.push(format!("{} {} {:016x}", name, seed, digest(&mut *generator)));
+        }
+    }
+    lines
+}
+
+#[test]
+fn worldgen_goldens() {
+    let actual = compute_goldens();
+    if env::var_os("BLESS").is_some() {
+        let mut contents = String::from("# generator seed digest - regenerate with `BLESS=1 cargo test worldgen_goldens`\n");
+        for line in &actual {
+            contents.push_str(line);
+            contents.push('\n');
+        }
+        fs::write(goldens_path(), contents).expect("couldn't write goldens");
+        return;
+    }
+
+    let contents = fs::read_to_string(goldens_path()).expect("couldn't read goldens");
+    let expected: Vec<&str> = contents.lines().filter(|line| !line.starts_with('#') && !line.is_empty()).collect();
+    let mismatches: Vec<String> = actual.iter()
+        .filter(|line| !expected.contains(&line.as_str()))
+        .cloned()
+        .collect();
+    assert!(
+        mismatches.is_empty() && expected.len() == actual.len(),
+        "generated terrain doesn't match goldens.txt - got {:?}\nif this is intentional, rerun with BLESS=1",
+        mismatches
+    );
+}
@@ -0,0 +1,360 @@
+use std::path::Path;
+use std::rc::Rc;
+use image;
+use world::{ChunkCoordinates, HashChunk, Chunk, CHUNK_SIZE};
+use worldgen::WorldGenerator;
+use worldgen::pipeline::{Pipeline, Stage, StageKind};
+use block;
+use block::BlockType;
+use block_registry::BlockRegistry;
+
+/// What happens to columns beyond the edges of the image
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum EdgeMode {
+    /// The image repeats forever in every direction
+    Tile,
+    /// Pixels along the edge of the image stretch out forever
+    Clamp,
+}
+
+impl EdgeMode {
+    /// Index of pixel `i` in a row or column `size` pixels long
+    fn wrap(&self, i: i64, size: usize) -> usize {
+        match *self {
+            EdgeMode::Tile => i.rem_euclid(size as i64) as usize,
+            EdgeMode::Clamp => i.max(0).min(size as i64 - 1) as usize,
+        }
+    }
+}
+
+/// Tunable parameters for `HeightmapWorldGenerator`
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeightmapParameters {
+    /// Width (in blocks) of each pixel
+    pub horizontal_scale: f32,
+    /// How many blocks higher a white pixel is than a black one
+    pub vertical_scale: f32,
+    /// World height of the surface of a black pixel
+    pub base_height: i32,
+    pub edges: EdgeMode,
+    /// How many blocks of subsurface (e.g. dirt) lie between the surface and the stone beneath
+    pub dirt_depth: i32,
+}
+
+impl Default for HeightmapParameters {
+    fn default() -> HeightmapParameters {
+        HeightmapParameters {
+            horizontal_scale: 1.0,
+            vertical_scale: 64.0,
+            base_height: -16,
+            edges: EdgeMode::Clamp,
+            dirt_depth: 3,
+        }
+    }
+}
+
+/// A grayscale image of terrain heights, from 0 (black) to 1 (white)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heightmap {
+    width: usize,
+    height: usize,
+    /// Rows of pixels from north (z=0) to south
+    pixels: Vec<f32>,
+}
+
+impl Heightmap {
+    pub fn new(width: usize, height: usize, pixels: Vec<f32>) -> Result<Heightmap, String> {
+        if width == 0 || height == 0 || pixels.len() != width * height {
+            return Err(format!("expected {}x{} pixels, got {}", width, height, pixels.len()));
+        }
+        Ok(Heightmap { width, height, pixels })
+    }
+
+    /// Load an image, converting it to grayscale if it isn't already
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Heightmap, String> {
+        let path = path.as_ref();
+        let image = image::open(path).map_err(|e| format!("couldn't read heightmap {}: {}", path.display(), e))?.to_luma16();
+        let pixels = image.pixels().map(|pixel| pixel[0] as f32 / u16::MAX as f32).collect();
+        Heightmap::new(image.width() as usize, image.height() as usize, pixels)
+    }
+
+    fn pixel(&self, x: i64, z: i64, edges: EdgeMode) -> f32 {
+        self.pixels[edges.wrap(z, self.height) * self.width + edges.wrap(x, self.width)]
+    }
+
+    /// Height at a point measured in pixels, blending smoothly between the nearest four pixels
+    fn sample(&self, x: f32, z: f32, edges: EdgeMode) -> f32 {
+        // pixel centres are at half coordinates
+        let (x, z) = (x - 0.5, z - 0.5);
+        let (x0, z0) = (x.floor(), z.floor());
+        let (tx, tz) = (x - x0, z - z0);
+        let (x0, z0) = (x0 as i64, z0 as i64);
+        let north = self.pixel(x0, z0, edges) * (1.0 - tx) + self.pixel(x0 + 1, z0, edges) * tx;
+        let south = self.pixel(x0, z0 + 1, edges) * (1.0 - tx) + self.pixel(x0 + 1, z0 + 1, edges) * tx;
+        north * (1.0 - tz) + south * tz
+    }
+}
+
+/// An image whose colours pick the surface block of each column - each pixel becomes whichever
+/// block type is closest to it in colour
+#[derive(Debug, Clone, PartialEq)]
+pub struct SurfaceMap {
+    width: usize,
+    height: usize,
+    blocks: Vec<&'static BlockType>,
+}
+
+impl SurfaceMap {
+    pub fn new(width: usize, height: usize, blocks: Vec<&'static BlockType>) -> Result<SurfaceMap, String> {
+        if width == 0 || height == 0 || blocks.len() != width * height {
+            return Err(format!("expected {}x{} pixels, got {}", width, height, blocks.len()));
+        }
+        Ok(SurfaceMap { width, height, blocks })
+    }
+
+    /// Load a colour map, matching its colours against every block in `blocks`
+    pub fn open<P: AsRef<Path>>(path: P, blocks: &BlockRegistry) -> Result<SurfaceMap, String> {
+        let path = path.as_ref();
+        let image = image::open(path).map_err(|e| format!("couldn't read colour map {}: {}", path.display(), e))?.to_rgb8();
+        let surface = image.pixels()
+            .map(|pixel| nearest_block([pixel[0] as f32 / 255.0, pixel[1] as f32 / 255.0, pixel[2] as f32 / 255.0], blocks))
+            .collect();
+        SurfaceMap::new(image.width() as usize, image.height() as usize, surface)
+    }
+
+    fn block(&self, x: i64, z: i64, edges: EdgeMode) -> &'static BlockType {
+        self.blocks[edges.wrap(z, self.height) * self.width + edges.wrap(x, self.width)]
+    }
+}
+
+/// The block type in `blocks` whose colour is closest to `color` - the first registered wins a
+/// tie, and an empty registry falls back to grass
+fn nearest_block(color: [f32; 3], blocks: &BlockRegistry) -> &'static BlockType {
+    let distance = |block_type: &BlockType| -> f32 {
+        block_type.color.iter().zip(color.iter()).map(|(a, b)| (a - b) * (a - b)).sum()
+    };
+    blocks.iter()
+        .map(|(_, block_type)| block_type)
+        .min_by(|a, b| distance(a).partial_cmp(&distance(b)).unwrap())
+        .unwrap_or(block::GRASS)
+}
+
+/// Heights and surface blocks shared by the stages of a `HeightmapWorldGenerator`
+struct HeightmapTerrain {
+    heightmap: Heightmap,
+    surface: Option<SurfaceMap>,
+    parameters: HeightmapParameters,
+}
+
+impl HeightmapTerrain {
+    /// World y coordinate of the surface block of the column at world (x, z)
+    fn surface_height(&self, x: i32, z: i32) -> i32 {
+        let scale = self.parameters.horizontal_scale;
+        // sample the middle of the column
+        let value = self.heightmap.sample((x as f32 + 0.5) / scale, (z as f32 + 0.5) / scale, self.parameters.edges);
+        self.parameters.base_height + (value * self.parameters.vertical_scale).round() as i32
+    }
+
+    /// Surface and subsurface blocks of the column at world (x, z)
+    fn surface_blocks(&self, x: i32, z: i32) -> (&'static BlockType, &'static BlockType) {
+        let surface = match self.surface {
+            Some(ref map) => {
+                let scale = self.parameters.horizontal_scale;
+                let (x, z) = ((x as f32 / scale).floor() as i64, (z as f32 / scale).floor() as i64);
+                map.block(x, z, self.parameters.edges)
+            },
+            None => block::GRASS,
+        };
+        // grass grows on dirt - anything else is the same all the way down to the stone
+        (surface, if surface == block::GRASS { block::DIRT } else { surface })
+    }
+
+    /// Call `f` with each column of the chunk at `coordinates`, along with its world x and z
+    fn each_column<F: FnMut(u8, u8, i32, i32)>(&self, coordinates: ChunkCoordinates, mut f: F) {
+        let size = CHUNK_SIZE as i32;
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                f(x, z, coordinates[0] * size + x as i32, coordinates[2] * size + z as i32);
+            }
+        }
+    }
+}
+
+/// Stone up to the height of each pixel
+struct HeightmapStage(Rc<HeightmapTerrain>);
+
+impl Stage for HeightmapStage {
+    fn name(&self) -> &'static str {
+        "terrain"
+    }
+
+    fn kind(&self) -> StageKind {
+        StageKind::BaseTerrain
+    }
+
+    fn apply(&mut self, coordinates: ChunkCoordinates, chunk: &mut HashChunk) {
+        let terrain = &self.0;
+        let bottom = coordinates[1] * CHUNK_SIZE as i32;
+        terrain.each_column(coordinates, |x, z, world_x, world_z| {
+            let height = terrain.surface_height(world_x, world_z);
+            for y in 0..CHUNK_SIZE {
+                if bottom + y as i32 <= height {
+                    chunk.set([x, y, z].into(), block::STONE);
+                }
+            }
+        });
+    }
+}
+
+/// Tops the stone with blocks from the colour map, or grass and dirt without one
+struct SurfaceMapStage(Rc<HeightmapTerrain>);
+
+impl Stage for SurfaceMapStage {
+    fn name(&self) -> &'static str {
+        "surface"
+    }
+
+    fn kind(&self) -> StageKind {
+        StageKind::SurfaceRules
+    }
+
+    fn apply(&mut self, coordinates: ChunkCoordinates, chunk: &mut HashChunk) {
+        let terrain = &self.0;
+        let bottom = coordinates[1] * CHUNK_SIZE as i32;
+        terrain.each_column(coordinates, |x, z, world_x, world_z| {
+            let height = terrain.surface_height(world_x, world_z);
+            let (surface, subsurface) = terrain.surface_blocks(world_x, world_z);
+            for y in 0..CHUNK_SIZE {
+                let world_y = bottom + y as i32;
+                if chunk.get([x, y, z].into()).is_none() || world_y < height - terrain.parameters.dirt_depth {
+                    continue;
+                }
+                if world_y == height {
+                    chunk.set([x, y, z].into(), surface);
+                } else if world_y < height {
+                    chunk.set([x, y, z].into(), subsurface);
+                }
+            }
+        });
+    }
+}
+
+/// Generates terrain from a grayscale image, so worlds can be painted by hand or built from real
+/// elevation data
+///
+/// Each pixel becomes a column (or a square of columns, at larger horizontal scales) whose height
+/// is the pixel's brightness. This is a pipeline of the stages `terrain` and `surface`.
+pub struct HeightmapWorldGenerator {
+    terrain: Rc<HeightmapTerrain>,
+    pipeline: Pipeline,
+}
+
+#[allow(dead_code)]
+impl HeightmapWorldGenerator {
+    pub fn new(heightmap: Heightmap, surface: Option<SurfaceMap>, parameters: HeightmapParameters) -> HeightmapWorldGenerator {
+        let terrain = Rc::new(HeightmapTerrain { heightmap, surface, parameters });
+        let pipeline = Pipeline::new()
+            .with_stage(HeightmapStage(terrain.clone()))
+            .with_stage(SurfaceMapStage(terrain.clone()));
+        HeightmapWorldGenerator { terrain, pipeline }
+    }
+
+    /// Load a heightmap image, and optionally a colour map image picking the surface blocks from
+    /// `blocks`
+    pub fn open<P: AsRef<Path>>(heightmap: P, colours: Option<P>, parameters: HeightmapParameters, blocks: &BlockRegistry) -> Result<HeightmapWorldGenerator, String> {
+        let heightmap = Heightmap::open(heightmap)?;
+        let surface = match colours {
+            Some(path) => Some(SurfaceMap::open(path, blocks)?),
+            None => None,
+        };
+        Ok(HeightmapWorldGenerator::new(heightmap, surface, parameters))
+    }
+
+    /// World y coordinate of the surface block of the column at world (x, z)
+    pub fn surface_height(&self, x: i32, z: i32) -> i32 {
+        self.terrain.surface_height(x, z)
+    }
+
+    /// The stages this generator runs, to reorder or add to
+    pub fn pipeline_mut(&mut self) -> &mut Pipeline {
+        &mut self.pipeline
+    }
+}
+
+impl WorldGenerator for HeightmapWorldGenerator {
+    fn generate_chunk(&mut self, coordinates: ChunkCoordinates) -> HashChunk {
+        self.pipeline.generate_chunk(coordinates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use image;
+    use block;
+    use block_registry::BlockRegistry;
+    use world::Chunk;
+    use worldgen::WorldGenerator;
+    use worldgen::heightmap::{EdgeMode, Heightmap, HeightmapParameters, HeightmapWorldGenerator, SurfaceMap};
+
+    /// A 2x2 image, black in the north west and white in the south east
+    fn heightmap() -> Heightmap {
+        Heightmap::new(2, 2, vec![0.0, 0.5, 0.5, 1.0]).unwrap()
+    }
+
+    fn parameters(edges: EdgeMode) -> HeightmapParameters {
+        HeightmapParameters { horizontal_scale: 4.0, vertical_scale: 16.0, base_height: 0, edges, dirt_depth: 3 }
+    }
+
+    #[test]
+    fn heightmap_pixels_to_heights() {
+        let generator = HeightmapWorldGenerator::new(heightmap(), None, parameters(EdgeMode::Clamp));
+        assert_eq!(generator.surface_height(0, 0), 0);
+        assert_eq!(generator.surface_height(7, 7), 16);
+        // halfway between pixels blends between them
+        assert_eq!(generator.surface_height(3, 0), 3);
+        // clamping keeps the edge pixels going forever
+        assert_eq!(generator.surface_height(-100, -100), 0);
+        assert_eq!(generator.surface_height(100, 100), 16);
+
+        let generator = HeightmapWorldGenerator::new(heightmap(), None, parameters(EdgeMode::Tile));
+        assert_eq!(generator.surface_height(-1, -1), generator.surface_height(7, 7));
+        assert_eq!(generator.surface_height(8, 8), generator.surface_height(0, 0));
+    }
+
+    #[test]
+    fn heightmap_colour_map_picks_surface() {
+        let surface = SurfaceMap::new(2, 2, vec![block::SAND, block::GRASS, block::SNOW, block::STONE]).unwrap();
+        let mut generator = HeightmapWorldGenerator::new(heightmap(), Some(surface), parameters(EdgeMode::Clamp));
+        let chunk = generator.generate_chunk([0, 0, 0].into());
+        assert_eq!(chunk.get([0, 0, 0].into()), Some(&block::SAND));
+        let height = generator.surface_height(4, 0) as u8;
+        assert_eq!(chunk.get([4, height, 0].into()), Some(&block::GRASS));
+        assert_eq!(chunk.get([4, height - 1, 0].into()), Some(&block::DIRT));
+        assert_eq!(chunk.get([4, height + 1, 0].into()), None);
+        assert!(Heightmap::new(2, 2, vec![0.0]).is_err());
+    }
+
+    #[test]
+    fn heightmap_open_png() {
+        let dir = env::temp_dir().join(format!("ave-heightmap-{}", ::std::process::id()));
+        ::std::fs::create_dir_all(&dir).unwrap();
+        let (heights, colours) = (dir.join("heights.png"), dir.join("colours.png"));
+        image::GrayImage::from_raw(2, 1, vec![0, 255]).unwrap().save(&heights).unwrap();
+        image::RgbImage::from_raw(2, 1, vec![240, 230, 140, 210, 210, 210]).unwrap().save(&colours).unwrap();
+
+        let mut blocks = BlockRegistry::default();
+        let generator = HeightmapWorldGenerator::open(&heights, Some(&colours), parameters(EdgeMode::Clamp), &blocks).unwrap();
+        assert_eq!(generator.surface_height(0, 0), 0);
+        assert_eq!(generator.surface_height(7, 0), 16);
+        assert_eq!(generator.terrain.surface_blocks(0, 0).0, block::SAND);
+        assert_eq!(generator.terrain.surface_blocks(7, 0).0, block::STONE);
+        // blocks loaded from a file are matched too - this one is closer to the grey than stone
+        blocks.parse("[[blocks]]\nname = \"marble\"\ncolor = [0.8235, 0.8235, 0.8235]\n").unwrap();
+        let generator = HeightmapWorldGenerator::open(&heights, Some(&colours), parameters(EdgeMode::Clamp), &blocks).unwrap();
+        assert_eq!(generator.terrain.surface_blocks(7, 0).0.name, "marble");
+        assert!(HeightmapWorldGenerator::open(dir.join("missing.png"), None, parameters(EdgeMode::Clamp), &blocks).is_err());
+        ::std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
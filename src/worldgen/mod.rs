@@ -0,0 +1,226 @@
+use world::{ChunkCoordinates, HashChunk, Chunk, CHUNK_SIZE};
+use rand::{Rng, StdRng, SeedableRng};
+use block;
+
+mod biome;
+mod caves;
+mod flat;
+mod fractal;
+mod heightmap;
+mod natural;
+mod ores;
+mod pipeline;
+mod registry;
+mod structures;
+#[cfg(test)]
+mod golden;
+
+#[allow(unused_imports)]
+pub use self::flat::{FlatWorldGenerator, FlatLayers, Layer};
+#[allow(unused_imports)]
+pub use self::natural::{NaturalWorldGenerator, NaturalParameters};
+#[allow(unused_imports)]
+pub use self::caves::CaveParameters;
+#[allow(unused_imports)]
+pub use self::fractal::{FractalParameters, HeightCurve};
+#[allow(unused_imports)]
+pub use self::heightmap::{HeightmapWorldGenerator, HeightmapParameters, Heightmap, SurfaceMap, EdgeMode};
+#[allow(unused_imports)]
+pub use self::biome::{Biome, BiomeParameters};
+#[allow(unused_imports)]
+pub use self::ores::{OreParameters, OreStage, default_ores};
+#[allow(unused_imports)]
+pub use self::structures::{Structure, StructureStage, Tree, PendingWrites};
+#[allow(unused_imports)]
+pub use self::pipeline::{Pipeline, Stage, StageKind};
+#[allow(unused_imports)]
+pub use self::registry::{GeneratorRegistry, GeneratorParameters, GeneratorFactory, ParameterReader};
+
+pub trait WorldGenerator {
+    /// Generate the chunk at `coordinates`
+    ///
+    /// The result must depend only on the generator's seed and `coordinates`, never on which
+    /// chunks were generated before it
+    fn generate_chunk(&mut self, coordinates: ChunkCoordinates) -> HashChunk;
+}
+
+/// 64-bit FNV-1a hash, which unlike `DefaultHasher` won't change between runs or Rust releases
+pub struct StableHasher {
+    hash: u64,
+}
+
+impl StableHasher {
+    pub fn new() -> StableHasher {
+        StableHasher { hash: 0xcbf2_9ce4_8422_2325 }
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.hash ^= *byte as u64;
+            self.hash = self.hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    pub fn write_i32(&mut self, value: i32) {
+        self.write(&value.to_le_bytes());
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+/// Stable hash of a seed and chunk coordinates
+///
+/// This is FNV-1a followed by a splitmix64 finalizer, so nearby chunks get very different values
+pub fn chunk_seed(seed: usize, coordinates: ChunkCoordinates) -> u64 {
+    let mut hasher = StableHasher::new();
+    hasher.write(&(seed as u64).to_le_bytes());
+    hasher.write_i32(coordinates[0]);
+    hasher.write_i32(coordinates[1]);
+    hasher.write_i32(coordinates[2]);
+    let mut hash = hasher.finish();
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+/// A random number in [0, 1) for the column at world (x, z), stable in the same way as `chunk_seed`
+pub fn column_random(seed: usize, x: i32, z: i32) -> f32 {
+    (chunk_seed(seed, [x, 0, z].into()) >> 40) as f32 / (1u64 << 24) as f32
+}
+
+/// A random number generator for a single chunk, seeded from the world seed and chunk coordinates
+pub fn chunk_rng(seed: usize, coordinates: ChunkCoordinates) -> StdRng {
+    let hash = chunk_seed(seed, coordinates);
+    let s: &[_] = &[hash as usize, (hash >> 32) as usize];
+    StdRng::from_seed(s)
+}
+
+/// A flat world generator with pillars of random height
+///
+/// This is a pipeline of the stages `ground` and `pillars`.
+#[allow(dead_code)]
+pub struct RandomPillarsWorldGenerator {
+    pipeline: Pipeline,
+}
+
+#[allow(dead_code)]
+impl RandomPillarsWorldGenerator {
+    pub fn new(seed: usize) -> RandomPillarsWorldGenerator {
+        let pipeline = Pipeline::new()
+            .with_stage(GrassGroundStage)
+            .with_stage(PillarStage { seed });
+        RandomPillarsWorldGenerator { pipeline }
+    }
+
+    /// The stages this generator runs, to reorder or add to
+    pub fn pipeline_mut(&mut self) -> &mut Pipeline {
+        &mut self.pipeline
+    }
+}
+
+impl WorldGenerator for RandomPillarsWorldGenerator {
+    fn generate_chunk(&mut self, coordinates: ChunkCoordinates) -> HashChunk {
+        self.pipeline.generate_chunk(coordinates)
+    }
+}
+
+/// A single layer of grass at y=0
+struct GrassGroundStage;
+
+impl Stage for GrassGroundStage {
+    fn name(&self) -> &'static str {
+        "ground"
+    }
+
+    fn kind(&self) -> StageKind {
+        StageKind::BaseTerrain
+    }
+
+    fn apply(&mut self, coordinates: ChunkCoordinates, chunk: &mut HashChunk) {
+        if coordinates[1] == 0 {
+            for x in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    chunk.set([x, 0, z].into(), block::GRASS);
+                }
+            }
+        }
+    }
+}
+
+/// A few pillars of dirt in every chunk
+struct PillarStage {
+    seed: usize,
+}
+
+impl Stage for PillarStage {
+    fn name(&self) -> &'static str {
+        "pillars"
+    }
+
+    fn kind(&self) -> StageKind {
+        StageKind::Feature
+    }
+
+    fn apply(&mut self, coordinates: ChunkCoordinates, chunk: &mut HashChunk) {
+        let mut prng = chunk_rng(self.seed, coordinates);
+        let number_of_pillars = prng.gen_range(1, 8);
+        for _ in 0..number_of_pillars {
+            let pillar_x = prng.gen_range(0, CHUNK_SIZE - 1);
+            let pillar_z = prng.gen_range(0, CHUNK_SIZE - 1);
+            let pillar_height = prng.gen_range(0, CHUNK_SIZE);
+            for y in 0..pillar_height {
+                chunk.set([pillar_x, y, pillar_z].into(), block::DIRT);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use world::{ChunkCoordinates, HashChunk};
+    use worldgen::{chunk_seed, WorldGenerator, RandomPillarsWorldGenerator, NaturalWorldGenerator};
+
+    fn generate_in_order(generator: &mut dyn WorldGenerator, order: &[ChunkCoordinates]) -> Vec<(ChunkCoordinates, HashChunk)> {
+        let mut chunks: Vec<_> = order.iter().map(|&coordinates| (coordinates, generator.generate_chunk(coordinates))).collect();
+        chunks.sort_by_key(|&(coordinates, _)| (coordinates[0], coordinates[1], coordinates[2]));
+        chunks
+    }
+
+    fn assert_order_independent<G: WorldGenerator, F: Fn() -> G>(new_generator: F) {
+        let mut order: Vec<ChunkCoordinates> = Vec::new();
+        for x in -1..2 {
+            for y in -1..2 {
+                for z in -1..2 {
+                    order.push([x, y, z].into());
+                }
+            }
+        }
+        let forwards = generate_in_order(&mut new_generator(), &order);
+        order.reverse();
+        let backwards = generate_in_order(&mut new_generator(), &order);
+        order.swap(3, 17);
+        order.swap(8, 21);
+        let shuffled = generate_in_order(&mut new_generator(), &order);
+        assert!(forwards == backwards);
+        assert!(forwards == shuffled);
+    }
+
+    #[test]
+    fn worldgen_chunk_seed_is_stable() {
+        assert_eq!(chunk_seed(42, [0, 0, 0].into()), chunk_seed(42, [0, 0, 0].into()));
+        assert!(chunk_seed(42, [0, 0, 0].into()) != chunk_seed(43, [0, 0, 0].into()));
+        assert!(chunk_seed(42, [1, 0, 0].into()) != chunk_seed(42, [0, 0, 1].into()));
+    }
+
+    #[test]
+    fn worldgen_random_pillars_order_independent() {
+        assert_order_independent(|| RandomPillarsWorldGenerator::new(42));
+    }
+
+    #[test]
+    fn worldgen_natural_order_independent() {
+        assert_order_independent(|| NaturalWorldGenerator::new(42));
+    }
+}
@@ -0,0 +1,553 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use world::{ChunkCoordinates, BlockCoordinates, HashChunk, Chunk, CHUNK_SIZE};
+use worldgen::{WorldGenerator, column_random};
+use worldgen::biome::{Biome, BiomeMap, BiomeParameters, BIOMES};
+use worldgen::caves::{Caves, CaveParameters};
+use worldgen::fractal::{FractalNoise, FractalParameters};
+use worldgen::ores::{OreParameters, OreStage, default_ores};
+use worldgen::pipeline::{Pipeline, Stage, StageKind};
+use worldgen::structures::{Structure, StructureStage, Tree};
+use block;
+use block::BlockType;
+
+/// Keeps trees from growing on exactly the columns which are decorated
+const TREE_SEED_OFFSET: usize = 0x7ee5;
+
+/// Tunable parameters for `NaturalWorldGenerator`
+#[derive(Debug, Clone, PartialEq)]
+pub struct NaturalParameters {
+    /// Shape of the hills
+    pub noise: FractalParameters,
+    /// Height (in blocks) the very highest peaks can reach above y=0, before biome scaling
+    pub amplitude: f32,
+    /// Surfaces at or above this height are bare stone
+    pub stone_height: i32,
+    /// How many blocks of subsurface (e.g. dirt) lie between the surface and the stone beneath
+    pub dirt_depth: i32,
+    /// Basins below this height fill with water
+    pub sea_level: i32,
+    /// Columns whose surface is within this many blocks of sea level are sandy beaches
+    pub beach_height: i32,
+    pub biomes: BiomeParameters,
+    /// Carve caves out of the ground, unless `None`
+    pub caves: Option<CaveParameters>,
+    /// Ores scattered through the stone
+    pub ores: Vec<OreParameters>,
+    /// Multiplies each biome's chance of trees - 0 for no trees at all
+    pub tree_density: f32,
+}
+
+impl Default for NaturalParameters {
+    fn default() -> NaturalParameters {
+        NaturalParameters {
+            noise: FractalParameters::default(),
+            amplitude: 128.0,
+            stone_height: 48,
+            dirt_depth: 3,
+            sea_level: 0,
+            beach_height: 2,
+            biomes: BiomeParameters::default(),
+            caves: Some(CaveParameters::default()),
+            ores: default_ores(),
+            tree_density: 1.0,
+        }
+    }
+}
+
+/// Generate a natural looking world
+///
+/// Terrain is a heightmap - each column is solid from its surface height all the way down, so
+/// hills and the ground beneath them span as many chunks vertically as they need. The biome of
+/// each column picks its surface blocks and decorations, and biomes' heights blend smoothly. Any
+/// column below sea level is topped up with water, with beaches around the shore. Trees are
+/// structures, so may spill over into neighbouring chunks.
+///
+/// This is a pipeline of the stages `terrain`, `surface`, `caves` (unless turned off),
+/// `decorations`, `ores` and `trees`.
+pub struct NaturalWorldGenerator {
+    terrain: Rc<NaturalTerrain>,
+    pipeline: Pipeline,
+}
+
+/// The surface of a single column
+struct Column {
+    height: i32,
+    surface: &'static BlockType,
+    subsurface: &'static BlockType,
+    /// Block type and height of anything placed on top of the surface
+    decoration: Option<(&'static BlockType, i32)>,
+    /// Chance of a tree growing on the column
+    trees: f32,
+}
+
+/// Chunk column (x, z) and its columns
+type ChunkColumns = ((i32, i32), Rc<Vec<Column>>);
+
+/// Heightmap and biomes shared by the stages of a `NaturalWorldGenerator`
+struct NaturalTerrain {
+    seed: usize,
+    noise: FractalNoise,
+    biomes: BiomeMap,
+    parameters: NaturalParameters,
+    /// Columns of the chunk column most recently asked for - every stage of a chunk wants them
+    cache: RefCell<Option<ChunkColumns>>,
+}
+
+impl NaturalWorldGenerator {
+    #[allow(dead_code)]
+    pub fn new(seed: usize) -> NaturalWorldGenerator {
+        NaturalWorldGenerator::with_parameters(seed, NaturalParameters::default())
+    }
+
+    pub fn with_parameters(seed: usize, parameters: NaturalParameters) -> NaturalWorldGenerator {
+        debug!("Using seed {} for NaturalWorldGenerator", seed);
+        let noise = FractalNoise::new(seed, parameters.noise.clone());
+        let biomes = BiomeMap::new(seed, parameters.biomes.clone());
+        let terrain = Rc::new(NaturalTerrain { seed, noise, biomes, parameters: parameters.clone(), cache: RefCell::new(None) });
+
+        let mut pipeline = Pipeline::new()
+            .with_stage(TerrainStage(terrain.clone()))
+            .with_stage(SurfaceStage(terrain.clone()));
+        if let Some(caves) = parameters.caves {
+            pipeline.push(Box::new(CaveStage { caves: Caves::new(seed, caves), terrain: terrain.clone() }));
+        }
+        let trees = terrain.clone();
+        let pipeline = pipeline
+            .with_stage(DecorationStage(terrain.clone()))
+            .with_stage(OreStage::new(seed, parameters.ores))
+            .with_stage(StructureStage::new("trees", move |x, z| trees.trees(x, z)));
+        NaturalWorldGenerator { terrain, pipeline }
+    }
+
+    /// Dominant biome of the column at world (x, z)
+    #[allow(dead_code)]
+    pub fn biome_at(&self, x: f32, z: f32) -> Biome {
+        self.terrain.biomes.biome_at(x, z)
+    }
+
+    /// World y coordinate of the surface block of the column at world (x, z)
+    #[allow(dead_code)]
+    pub fn surface_height(&self, x: f32, z: f32) -> i32 {
+        self.terrain.column(x, z).height
+    }
+
+    /// The stages this generator runs, to reorder or add to
+    #[allow(dead_code)]
+    pub fn pipeline_mut(&mut self) -> &mut Pipeline {
+        &mut self.pipeline
+    }
+}
+
+impl WorldGenerator for NaturalWorldGenerator {
+    fn generate_chunk(&mut self, coordinates: ChunkCoordinates) -> HashChunk {
+        self.pipeline.generate_chunk(coordinates)
+    }
+}
+
+impl NaturalTerrain {
+    fn column(&self, x: f32, z: f32) -> Column {
+        // https://www.redblobgames.com/maps/terrain-from-noise/ is a good source for tips
+        let hills = self.noise.get(x, z) * self.parameters.amplitude;
+
+        let weights = self.biomes.weights(x, z);
+        let mut height = 0.0;
+        let mut dominant = 0;
+        for (i, (weight, biome)) in weights.iter().zip(BIOMES.iter()).enumerate() {
+            let properties = biome.properties();
+            height += weight * (properties.height_offset + properties.height_scale * hills);
+            if *weight > weights[dominant] {
+                dominant = i;
+            }
+        }
+
+        let height = height.floor() as i32;
+        let properties = BIOMES[dominant].properties();
+        let sea_level = self.parameters.sea_level;
+        if height < sea_level - self.parameters.beach_height {
+            // sea floor
+            return Column { height, surface: properties.subsurface, subsurface: properties.subsurface, decoration: None, trees: 0.0 };
+        }
+        if height <= sea_level + self.parameters.beach_height {
+            return Column { height, surface: block::SAND, subsurface: block::SAND, decoration: None, trees: 0.0 };
+        }
+
+        let mut decoration = None;
+        let mut roll = column_random(self.seed, x as i32, z as i32);
+        for candidate in properties.decorations {
+            if roll < candidate.chance {
+                decoration = Some((candidate.block_type, candidate.height));
+                break;
+            }
+            roll -= candidate.chance;
+        }
+        Column {
+            height,
+            surface: properties.surface,
+            subsurface: properties.subsurface,
+            decoration,
+            trees: properties.trees * self.parameters.tree_density,
+        }
+    }
+
+    /// Columns of the chunk column at (x, z), indexed by `x * CHUNK_SIZE + z`
+    fn columns(&self, chunk_x: i32, chunk_z: i32) -> Rc<Vec<Column>> {
+        if let Some((key, ref columns)) = *self.cache.borrow() {
+            if key == (chunk_x, chunk_z) {
+                return columns.clone();
+            }
+        }
+        let size = CHUNK_SIZE as i32;
+        let mut columns = Vec::with_capacity((size * size) as usize);
+        for x in chunk_x * size..(chunk_x + 1) * size {
+            for z in chunk_z * size..(chunk_z + 1) * size {
+                columns.push(self.column(x as f32, z as f32));
+            }
+        }
+        let columns = Rc::new(columns);
+        *self.cache.borrow_mut() = Some(((chunk_x, chunk_z), columns.clone()));
+        columns
+    }
+
+    /// Call `f` with every position in the chunk at `coordinates`, along with its world
+    /// coordinates and the column it's in
+    fn each_block<F: FnMut(BlockCoordinates, [i32; 3], &Column)>(&self, coordinates: ChunkCoordinates, mut f: F) {
+        let size = CHUNK_SIZE as i32;
+        let columns = self.columns(coordinates[0], coordinates[2]);
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                let column = &columns[x as usize * CHUNK_SIZE as usize + z as usize];
+                for y in 0..CHUNK_SIZE {
+                    let world = [coordinates[0] * size + x as i32, coordinates[1] * size + y as i32, coordinates[2] * size + z as i32];
+                    f([x, y, z].into(), world, column);
+                }
+            }
+        }
+    }
+
+    /// Stone up to the surface, then water up to sea level
+    fn ground_at(&self, column: &Column, y: i32) -> Option<&'static BlockType> {
+        if y <= column.height {
+            Some(block::STONE)
+        } else if y <= self.parameters.sea_level {
+            Some(block::WATER)
+        } else {
+            None
+        }
+    }
+
+    /// What the surface rules turn the ground at height `y` into, if anything
+    fn surface_at(&self, column: &Column, y: i32) -> Option<&'static BlockType> {
+        if y > column.height || y >= self.parameters.stone_height {
+            None  // high peaks are bare stone
+        } else if y == column.height {
+            Some(column.surface)
+        } else if y >= column.height - self.parameters.dirt_depth {
+            Some(column.subsurface)
+        } else {
+            None
+        }
+    }
+
+    fn decoration_at(&self, column: &Column, y: i32) -> Option<&'static BlockType> {
+        match column.decoration {
+            Some((block_type, height)) if y > column.height && y <= column.height + height => Some(block_type),
+            _ => None,
+        }
+    }
+
+    /// Block at world height `y` in a column, before any caves, ores or trees
+    #[cfg(test)]
+    fn block_at(&self, column: &Column, y: i32) -> Option<&'static BlockType> {
+        self.decoration_at(column, y)
+            .or_else(|| self.surface_at(column, y))
+            .or_else(|| self.ground_at(column, y))
+    }
+
+    /// Trees rooted in the chunk column at (x, z)
+    fn trees(&self, chunk_x: i32, chunk_z: i32) -> Vec<Box<dyn Structure>> {
+        let size = CHUNK_SIZE as i32;
+        let mut trees: Vec<Box<dyn Structure>> = Vec::new();
+        for x in chunk_x * size..(chunk_x + 1) * size {
+            for z in chunk_z * size..(chunk_z + 1) * size {
+                let column = self.column(x as f32, z as f32);
+                if column.decoration.is_some() || column.height >= self.parameters.stone_height {
+                    continue;
+                }
+                if column_random(self.seed.wrapping_add(TREE_SEED_OFFSET), x, z) < column.trees {
+                    trees.push(Box::new(Tree::new(self.seed, x, column.height + 1, z)));
+                }
+            }
+        }
+        trees
+    }
+}
+
+/// Stone up to the surface of each column, and water above it up to sea level
+struct TerrainStage(Rc<NaturalTerrain>);
+
+impl Stage for TerrainStage {
+    fn name(&self) -> &'static str {
+        "terrain"
+    }
+
+    fn kind(&self) -> StageKind {
+        StageKind::BaseTerrain
+    }
+
+    fn apply(&mut self, coordinates: ChunkCoordinates, chunk: &mut HashChunk) {
+        let terrain = &self.0;
+        terrain.each_block(coordinates, |position, [_, y, _], column| {
+            if let Some(block_type) = terrain.ground_at(column, y) {
+                chunk.set(position, block_type);
+            }
+        });
+    }
+}
+
+/// Tops the ground with each biome's surface and subsurface blocks, or sand around the shore
+struct SurfaceStage(Rc<NaturalTerrain>);
+
+impl Stage for SurfaceStage {
+    fn name(&self) -> &'static str {
+        "surface"
+    }
+
+    fn kind(&self) -> StageKind {
+        StageKind::SurfaceRules
+    }
+
+    fn apply(&mut self, coordinates: ChunkCoordinates, chunk: &mut HashChunk) {
+        let terrain = &self.0;
+        terrain.each_block(coordinates, |position, [_, y, _], column| {
+            if chunk.get(position).is_some() {
+                if let Some(block_type) = terrain.surface_at(column, y) {
+                    chunk.set(position, block_type);
+                }
+            }
+        });
+    }
+}
+
+/// Carves caves out of the ground
+struct CaveStage {
+    caves: Caves,
+    terrain: Rc<NaturalTerrain>,
+}
+
+impl Stage for CaveStage {
+    fn name(&self) -> &'static str {
+        "caves"
+    }
+
+    fn kind(&self) -> StageKind {
+        StageKind::Carver
+    }
+
+    fn apply(&mut self, coordinates: ChunkCoordinates, chunk: &mut HashChunk) {
+        let caves = &self.caves;
+        self.terrain.each_block(coordinates, |position, [x, y, z], column| {
+            if chunk.get(position).is_some() && caves.is_cave(x, y, z, column.height) {
+                chunk.remove(position);
+            }
+        });
+    }
+}
+
+/// Places each biome's decorations, e.g. cacti, on top of the surface
+struct DecorationStage(Rc<NaturalTerrain>);
+
+impl Stage for DecorationStage {
+    fn name(&self) -> &'static str {
+        "decorations"
+    }
+
+    fn kind(&self) -> StageKind {
+        StageKind::Feature
+    }
+
+    fn apply(&mut self, coordinates: ChunkCoordinates, chunk: &mut HashChunk) {
+        let terrain = &self.0;
+        terrain.each_block(coordinates, |position, [_, y, _], column| {
+            if let Some(block_type) = terrain.decoration_at(column, y) {
+                chunk.set(position, block_type);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use block;
+    use world::{Chunk, CHUNK_SIZE};
+    use worldgen::{WorldGenerator, NaturalWorldGenerator, NaturalParameters};
+    use worldgen::caves::{Caves, CaveParameters};
+
+    /// Just the heightmap, without caves, ores or trees
+    fn terrain_only() -> NaturalParameters {
+        NaturalParameters { caves: None, ores: Vec::new(), tree_density: 0.0, ..NaturalParameters::default() }
+    }
+
+    #[test]
+    fn natural_ground_is_solid_below_surface() {
+        let mut generator = NaturalWorldGenerator::with_parameters(42, terrain_only());
+        let chunk = generator.generate_chunk([0, -4, 0].into());
+        assert_eq!(chunk.blocks.len(), (CHUNK_SIZE as usize).pow(3));
+        assert!(chunk.blocks.values().all(|&block_type| block_type == block::STONE));
+    }
+
+    #[test]
+    fn natural_surface_spans_chunks() {
+        let parameters = NaturalParameters { amplitude: 1024.0, ..terrain_only() };
+        let mut generator = NaturalWorldGenerator::with_parameters(42, parameters);
+        let size = CHUNK_SIZE as i32;
+        let mut tallest = (0, 0, 0);
+        for x in 0..size {
+            for z in 0..size {
+                let height = generator.surface_height(x as f32, z as f32);
+                if height > tallest.2 {
+                    tallest = (x, z, height);
+                }
+            }
+        }
+        let (x, z, height) = tallest;
+        assert!(height > size, "expected a peak taller than a chunk, got {}", height);
+
+        // the peak and the column beneath it are solid, even across chunk borders
+        let top_chunk = generator.generate_chunk([0, height.div_euclid(size), 0].into());
+        let below_chunk = generator.generate_chunk([0, height.div_euclid(size) - 1, 0].into());
+        let local = |y: i32| [x as u8, y.rem_euclid(size) as u8, z as u8].into();
+        assert!(top_chunk.get(local(height)).is_some());
+        if height.rem_euclid(size) < size - 1 {
+            assert!(top_chunk.get(local(height + 1)).is_none());
+        }
+        for y in 0..size {
+            assert!(below_chunk.get(local(y)).is_some());
+        }
+    }
+
+    #[test]
+    fn natural_surface_follows_biome() {
+        let generator = NaturalWorldGenerator::with_parameters(42, terrain_only());
+        for x in -20..20 {
+            for z in -20..20 {
+                let (x, z) = (x as f32 * 50.0, z as f32 * 50.0);
+                let biome = generator.biome_at(x, z).properties();
+                let column = generator.terrain.column(x, z);
+                let shore = generator.terrain.parameters.sea_level + generator.terrain.parameters.beach_height;
+                if column.height > shore && column.height < generator.terrain.parameters.stone_height {
+                    assert_eq!(generator.terrain.block_at(&column, column.height), Some(biome.surface));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn natural_biomes_blend_smoothly() {
+        // without hills, heights come only from biome offsets - which differ by up to 32 blocks
+        let parameters = NaturalParameters { amplitude: 0.0, ..terrain_only() };
+        let generator = NaturalWorldGenerator::with_parameters(42, parameters);
+        let mut biomes = HashSet::new();
+        let mut previous = generator.surface_height(0.0, 0.0);
+        for x in 1..4000 {
+            biomes.insert(generator.biome_at(x as f32, 0.0));
+            let height = generator.surface_height(x as f32, 0.0);
+            assert!((height - previous).abs() <= 1, "cliff at x={}: {} -> {}", x, previous, height);
+            previous = height;
+        }
+        assert!(biomes.len() > 1);
+    }
+
+    #[test]
+    fn natural_water_fills_basins_to_sea_level() {
+        let parameters = NaturalParameters { sea_level: 10, ..terrain_only() };
+        let generator = NaturalWorldGenerator::with_parameters(42, parameters);
+        let (mut seabed, mut beach) = (false, false);
+        for x in -50..50 {
+            for z in -50..50 {
+                let (x, z) = (x as f32 * 20.0, z as f32 * 20.0);
+                let column = generator.terrain.column(x, z);
+                if column.height < 10 {
+                    seabed = true;
+                    assert_eq!(generator.terrain.block_at(&column, 10), Some(block::WATER));
+                    assert_eq!(generator.terrain.block_at(&column, column.height + 1), Some(block::WATER));
+                    assert!(generator.terrain.block_at(&column, column.height) != Some(block::WATER));
+                }
+                if (column.height - 10).abs() <= 2 {
+                    beach = true;
+                    assert_eq!(generator.terrain.block_at(&column, column.height), Some(block::SAND));
+                }
+                assert_eq!(generator.terrain.block_at(&column, column.height.max(10) + 5), None);
+            }
+        }
+        assert!(seabed && beach);
+    }
+
+    #[test]
+    fn natural_stages_run_on_their_own() {
+        let mut generator = NaturalWorldGenerator::new(42);
+        assert_eq!(generator.pipeline_mut().names(), vec!["terrain", "surface", "caves", "decorations", "ores", "trees"]);
+        generator.pipeline_mut().configure("terrain").unwrap();
+        let chunk = generator.generate_chunk([0, 0, 0].into());
+        assert!(!chunk.blocks.is_empty());
+        assert!(chunk.blocks.values().all(|&block_type| block_type == block::STONE || block_type == block::WATER));
+
+        let mut generator = NaturalWorldGenerator::new(42);
+        generator.pipeline_mut().configure("terrain, surface").unwrap();
+        let chunk = generator.generate_chunk([0, 0, 0].into());
+        assert!(chunk.blocks.values().any(|&block_type| block_type == block::GRASS || block_type == block::SAND));
+    }
+
+    #[test]
+    fn natural_trees_spill_across_chunk_borders() {
+        let parameters = NaturalParameters { tree_density: 20.0, ..terrain_only() };
+        let generate = |order: &[[i32; 3]]| {
+            let mut generator = NaturalWorldGenerator::with_parameters(42, parameters.clone());
+            let mut chunks: Vec<_> = order.iter().map(|&coordinates| (coordinates, generator.generate_chunk(coordinates.into()))).collect();
+            chunks.sort_by_key(|&(coordinates, _)| coordinates);
+            chunks
+        };
+        let forwards = generate(&[[-1, 0, 0], [0, 0, 0]]);
+        let backwards = generate(&[[0, 0, 0], [-1, 0, 0]]);
+        assert!(forwards == backwards);
+
+        let (west, east) = (&forwards[0].1, &forwards[1].1);
+        assert!(east.blocks.values().any(|&block_type| block_type == block::WOOD));
+        // some tree close to the border has leaves on both sides of it
+        let last = CHUNK_SIZE - 1;
+        let crossing = (0..CHUNK_SIZE).any(|y| (0..CHUNK_SIZE).any(|z| {
+            west.get([last, y, z].into()) == Some(&block::LEAVES) && east.get([0, y, z].into()) == Some(&block::LEAVES)
+        }));
+        assert!(crossing);
+    }
+
+    #[test]
+    fn natural_caves_carve_underground() {
+        let mut generator = NaturalWorldGenerator::new(42);
+        let chunk = generator.generate_chunk([0, -2, 0].into());
+        let solid = (CHUNK_SIZE as usize).pow(3);
+        assert!(chunk.blocks.len() < solid, "expected some caves in a solid chunk");
+        assert!(chunk.blocks.len() > solid / 2, "expected caves to leave most of the ground solid");
+    }
+
+    #[test]
+    fn natural_caves_match_across_chunk_borders() {
+        let caves = CaveParameters { min_y: -1024, ..CaveParameters::default() };
+        let parameters = NaturalParameters { caves: Some(caves.clone()), ..NaturalParameters::default() };
+        let mut generator = NaturalWorldGenerator::with_parameters(7, parameters);
+        let reference = Caves::new(7, caves);
+        let size = CHUNK_SIZE as i32;
+        // the faces either side of the border between two deep chunks
+        let west = generator.generate_chunk([-1, -3, 0].into());
+        let east = generator.generate_chunk([0, -3, 0].into());
+        for y in 0..size {
+            for z in 0..size {
+                let world_y = -3 * size + y;
+                let local = |x: i32| [x as u8, y as u8, z as u8].into();
+                assert_eq!(west.get(local(size - 1)).is_none(), reference.is_cave(-1, world_y, z, 0));
+                assert_eq!(east.get(local(0)).is_none(), reference.is_cave(0, world_y, z, 0));
+            }
+        }
+    }
+}
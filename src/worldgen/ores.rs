@@ -0,0 +1,146 @@
+use rand::Rng;
+use world::{ChunkCoordinates, HashChunk, Chunk, BlockCoordinates, CHUNK_SIZE};
+use worldgen::chunk_rng;
+use worldgen::pipeline::{Stage, StageKind};
+use block;
+use block::BlockType;
+
+/// Keeps ore placement independent of anything else drawing from the chunk's random numbers
+const ORE_SEED_OFFSET: usize = 0x04e5;
+
+/// How and where one kind of ore is scattered through the stone
+#[derive(Debug, Clone, PartialEq)]
+pub struct OreParameters {
+    pub block_type: &'static BlockType,
+    /// Veins only start between these world heights
+    pub min_y: i32,
+    pub max_y: i32,
+    /// Number of blocks a vein wanders through - blocks that aren't stone are left alone
+    pub vein_size: u32,
+    /// Average number of veins per chunk which lies entirely within the height range
+    pub veins_per_chunk: f32,
+}
+
+/// Coal is common and shallow, gold rare and deep
+pub fn default_ores() -> Vec<OreParameters> {
+    vec![
+        OreParameters { block_type: block::COAL_ORE, min_y: -128, max_y: 64, vein_size: 12, veins_per_chunk: 8.0 },
+        OreParameters { block_type: block::IRON_ORE, min_y: -192, max_y: 0, vein_size: 8, veins_per_chunk: 5.0 },
+        OreParameters { block_type: block::GOLD_ORE, min_y: -256, max_y: -64, vein_size: 6, veins_per_chunk: 1.5 },
+    ]
+}
+
+/// Scatter ore veins through the stone of a chunk
+///
+/// Each chunk's veins are drawn from its own random numbers, so a chunk's ores are the same
+/// whichever order chunks are generated in. Veins are clipped at the chunk's edges.
+pub fn place_ores(seed: usize, coordinates: ChunkCoordinates, chunk: &mut HashChunk, ores: &[OreParameters]) {
+    let mut rng = chunk_rng(seed.wrapping_add(ORE_SEED_OFFSET), coordinates);
+    let size = CHUNK_SIZE as i32;
+    let bottom = coordinates[1] * size;
+    for ore in ores {
+        // only the part of the height range overlapping this chunk
+        let low = ore.min_y.max(bottom);
+        let high = ore.max_y.min(bottom + size - 1);
+        let overlap = (high - low + 1).max(0) as f32 / size as f32;
+        let expected = ore.veins_per_chunk * overlap;
+        let roll = rng.gen::<f32>();
+        let veins = expected.floor() as u32 + if roll < expected.fract() { 1 } else { 0 };
+        for _ in 0..veins {
+            let mut x = rng.gen_range(0, size);
+            let mut y = rng.gen_range(low, high + 1) - bottom;
+            let mut z = rng.gen_range(0, size);
+            for _ in 0..ore.vein_size {
+                if x >= 0 && x < size && y >= 0 && y < size && z >= 0 && z < size {
+                    let position: BlockCoordinates = [x as u8, y as u8, z as u8].into();
+                    if chunk.get(position) == Some(&block::STONE) {
+                        chunk.set(position, ore.block_type);
+                    }
+                }
+                match rng.gen_range(0, 6) {
+                    0 => x += 1,
+                    1 => x -= 1,
+                    2 => y += 1,
+                    3 => y -= 1,
+                    4 => z += 1,
+                    _ => z -= 1,
+                }
+            }
+        }
+    }
+}
+
+/// Scatters ore veins through the stone left by earlier stages
+pub struct OreStage {
+    seed: usize,
+    ores: Vec<OreParameters>,
+}
+
+impl OreStage {
+    pub fn new(seed: usize, ores: Vec<OreParameters>) -> OreStage {
+        OreStage { seed, ores }
+    }
+}
+
+impl Stage for OreStage {
+    fn name(&self) -> &'static str {
+        "ores"
+    }
+
+    fn kind(&self) -> StageKind {
+        StageKind::Feature
+    }
+
+    fn apply(&mut self, coordinates: ChunkCoordinates, chunk: &mut HashChunk) {
+        place_ores(self.seed, coordinates, chunk, &self.ores);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use block;
+    use world::{HashChunk, Chunk, CHUNK_SIZE};
+    use worldgen::ores::{place_ores, default_ores, OreParameters};
+
+    fn stone_chunk() -> HashChunk {
+        let mut chunk = HashChunk::new();
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    chunk.set([x, y, z].into(), block::STONE);
+                }
+            }
+        }
+        chunk
+    }
+
+    #[test]
+    fn ores_are_deterministic_per_chunk() {
+        let mut a = stone_chunk();
+        let mut b = stone_chunk();
+        place_ores(42, [3, -3, 1].into(), &mut a, &default_ores());
+        place_ores(42, [3, -3, 1].into(), &mut b, &default_ores());
+        assert!(a == b);
+        assert!(a.blocks.values().any(|&block_type| block_type == block::GOLD_ORE || block_type == block::IRON_ORE));
+    }
+
+    #[test]
+    fn ores_respect_height_range() {
+        let ores = vec![OreParameters { block_type: block::GOLD_ORE, min_y: -40, max_y: -36, vein_size: 1, veins_per_chunk: 50.0 }];
+        let mut chunk = stone_chunk();
+        place_ores(7, [0, -2, 0].into(), &mut chunk, &ores);
+        let mut found = 0;
+        for (position, &block_type) in chunk.blocks.iter() {
+            if block_type == block::GOLD_ORE {
+                found += 1;
+                let y = -2 * CHUNK_SIZE as i32 + position[1] as i32;
+                assert!((-40..=-36).contains(&y), "gold at y={}", y);
+            }
+        }
+        assert!(found > 0);
+
+        let mut chunk = stone_chunk();
+        place_ores(7, [0, 0, 0].into(), &mut chunk, &ores);
+        assert!(chunk.blocks.values().all(|&block_type| block_type == block::STONE));
+    }
+}
@@ -0,0 +1,170 @@
+use world::{ChunkCoordinates, HashChunk};
+use worldgen::WorldGenerator;
+
+/// What part of generation a stage plays - stages usually run in this order
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[allow(dead_code)]
+pub enum StageKind {
+    /// Lays down the bulk of the ground, e.g. stone up to the heightmap
+    BaseTerrain,
+    /// Replaces the top of the ground, e.g. grass and dirt over stone
+    SurfaceRules,
+    /// Removes blocks, e.g. caves
+    Carver,
+    /// Small things which fit inside a chunk, e.g. ores and cacti
+    Feature,
+    /// Things which may spill over into neighbouring chunks, e.g. trees
+    Structure,
+}
+
+/// A single step of generating a chunk
+///
+/// Like `WorldGenerator::generate_chunk`, what a stage does to a chunk must depend only on the
+/// seed, the chunk's coordinates and what earlier stages put in it.
+pub trait Stage {
+    /// Name used to pick out and reorder the stage, e.g. `caves`
+    fn name(&self) -> &'static str;
+    fn kind(&self) -> StageKind;
+    fn apply(&mut self, coordinates: ChunkCoordinates, chunk: &mut HashChunk);
+}
+
+/// Generates chunks by running named stages one after another
+///
+/// Stages can be added, removed and reordered in code, or from a comma separated list of names
+/// with `configure`.
+pub struct Pipeline {
+    stages: Vec<Box<dyn Stage>>,
+}
+
+#[allow(dead_code)]
+impl Pipeline {
+    pub fn new() -> Pipeline {
+        Pipeline { stages: Vec::new() }
+    }
+
+    /// Add a stage to the end of the pipeline
+    pub fn with_stage<S: Stage + 'static>(mut self, stage: S) -> Pipeline {
+        self.push(Box::new(stage));
+        self
+    }
+
+    pub fn push(&mut self, stage: Box<dyn Stage>) {
+        self.stages.push(stage);
+    }
+
+    /// Insert a stage just before the stage named `before`
+    pub fn insert_before(&mut self, before: &str, stage: Box<dyn Stage>) -> Result<(), String> {
+        let index = self.position(before)?;
+        self.stages.insert(index, stage);
+        Ok(())
+    }
+
+    /// Take the stage named `name` out of the pipeline
+    pub fn remove(&mut self, name: &str) -> Result<Box<dyn Stage>, String> {
+        let index = self.position(name)?;
+        Ok(self.stages.remove(index))
+    }
+
+    /// Names of the stages, in the order they run
+    pub fn names(&self) -> Vec<&'static str> {
+        self.stages.iter().map(|stage| stage.name()).collect()
+    }
+
+    /// Run only the stages named in `order`, in that order - any others are dropped
+    pub fn reorder(&mut self, order: &[&str]) -> Result<(), String> {
+        for (i, name) in order.iter().enumerate() {
+            if order[..i].contains(name) {
+                return Err(format!("stage {:?} is listed twice", name));
+            }
+        }
+        let mut stages = Vec::new();
+        for name in order {
+            let index = self.position(name)?;
+            stages.push(index);
+        }
+        let mut old: Vec<Option<Box<dyn Stage>>> = self.stages.drain(..).map(Some).collect();
+        self.stages = stages.into_iter().map(|index| old[index].take().unwrap()).collect();
+        for pair in self.stages.windows(2) {
+            if pair[1].kind() < pair[0].kind() {
+                warn!("stage {} ({:?}) now runs after {} ({:?})", pair[1].name(), pair[1].kind(), pair[0].name(), pair[0].kind());
+            }
+        }
+        Ok(())
+    }
+
+    /// Reorder from a comma separated list of stage names, e.g. `terrain,surface,ores`
+    pub fn configure(&mut self, order: &str) -> Result<(), String> {
+        let order: Vec<&str> = order.split(',').map(str::trim).collect();
+        self.reorder(&order)
+    }
+
+    fn position(&self, name: &str) -> Result<usize, String> {
+        self.stages.iter().position(|stage| stage.name() == name)
+            .ok_or_else(|| format!("no stage named {:?} - expected one of {:?}", name, self.names()))
+    }
+}
+
+impl WorldGenerator for Pipeline {
+    fn generate_chunk(&mut self, coordinates: ChunkCoordinates) -> HashChunk {
+        let mut chunk = HashChunk::new();
+        for stage in self.stages.iter_mut() {
+            stage.apply(coordinates, &mut chunk);
+        }
+        chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use block;
+    use block::BlockType;
+    use world::{ChunkCoordinates, HashChunk, Chunk};
+    use worldgen::WorldGenerator;
+    use worldgen::pipeline::{Pipeline, Stage, StageKind};
+
+    /// Sets a single block, replacing whatever was there
+    struct Fill(&'static str, &'static BlockType);
+
+    impl Stage for Fill {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn kind(&self) -> StageKind {
+            StageKind::BaseTerrain
+        }
+
+        fn apply(&mut self, _coordinates: ChunkCoordinates, chunk: &mut HashChunk) {
+            chunk.set([0, 0, 0].into(), self.1);
+        }
+    }
+
+    fn pipeline() -> Pipeline {
+        Pipeline::new()
+            .with_stage(Fill("stone", block::STONE))
+            .with_stage(Fill("dirt", block::DIRT))
+            .with_stage(Fill("sand", block::SAND))
+    }
+
+    #[test]
+    fn pipeline_runs_stages_in_order() {
+        let mut pipeline = pipeline();
+        assert_eq!(pipeline.generate_chunk([0, 0, 0].into()).get([0, 0, 0].into()), Some(&block::SAND));
+        pipeline.configure("sand, dirt").unwrap();
+        assert_eq!(pipeline.names(), vec!["sand", "dirt"]);
+        assert_eq!(pipeline.generate_chunk([0, 0, 0].into()).get([0, 0, 0].into()), Some(&block::DIRT));
+    }
+
+    #[test]
+    fn pipeline_edits() {
+        let mut pipeline = pipeline();
+        pipeline.remove("dirt").unwrap();
+        pipeline.insert_before("stone", Box::new(Fill("snow", block::SNOW))).unwrap();
+        assert_eq!(pipeline.names(), vec!["snow", "stone", "sand"]);
+        assert!(pipeline.remove("dirt").is_err());
+        assert!(pipeline.configure("stone,lava").is_err());
+        assert!(pipeline.configure("stone,stone").is_err());
+        // a failed reorder leaves the pipeline as it was
+        assert_eq!(pipeline.names(), vec!["snow", "stone", "sand"]);
+    }
+}
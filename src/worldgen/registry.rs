@@ -0,0 +1,259 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
+use block_registry::BlockRegistry;
+use worldgen::{WorldGenerator, RandomPillarsWorldGenerator, Pipeline, HeightCurve};
+use worldgen::flat::{FlatWorldGenerator, DEFAULT_PRESET};
+use worldgen::natural::{NaturalWorldGenerator, NaturalParameters};
+use worldgen::heightmap::{HeightmapWorldGenerator, HeightmapParameters, EdgeMode};
+
+/// Generator specific parameters by name, e.g. `sea_level = "4"` - as read from settings or the
+/// command line, so everything is a string until a factory parses it
+pub type GeneratorParameters = HashMap<String, String>;
+
+/// Builds a generator from a seed and its parameters
+pub type GeneratorFactory = Box<dyn Fn(usize, &ParameterReader) -> Result<Box<dyn WorldGenerator>, String>>;
+
+/// Parses the parameters handed to a factory, keeping track of which it asked for so that
+/// misspelt or unsupported ones can be reported
+pub struct ParameterReader<'a> {
+    parameters: &'a GeneratorParameters,
+    blocks: &'a BlockRegistry,
+    used: RefCell<HashSet<&'a str>>,
+}
+
+impl<'a> ParameterReader<'a> {
+    pub fn new(parameters: &'a GeneratorParameters, blocks: &'a BlockRegistry) -> ParameterReader<'a> {
+        ParameterReader { parameters, blocks, used: RefCell::new(HashSet::new()) }
+    }
+
+    /// Every block type a generator may use, for looking up blocks named in parameters
+    pub fn blocks(&self) -> &'a BlockRegistry {
+        self.blocks
+    }
+
+    /// The raw value of a parameter, if it was given
+    pub fn get_str(&self, name: &str) -> Option<&'a str> {
+        self.parameters.get_key_value(name).map(|(key, value)| {
+            self.used.borrow_mut().insert(key.as_str());
+            value.as_str()
+        })
+    }
+
+    /// Parse a parameter, or fall back to `default` if it wasn't given
+    pub fn get<T: FromStr>(&self, name: &str, default: T) -> Result<T, String> {
+        match self.get_str(name) {
+            Some(value) => value.trim().parse().map_err(|_| format!("invalid value {:?} for parameter {}", value, name)),
+            None => Ok(default),
+        }
+    }
+
+    /// Reorder (or drop) the stages of a generator's pipeline, if a `stages` parameter was given
+    pub fn configure_stages(&self, pipeline: &mut Pipeline) -> Result<(), String> {
+        match self.get_str("stages") {
+            Some(order) => pipeline.configure(order),
+            None => Ok(()),
+        }
+    }
+
+    /// Names of parameters which were given but never asked for, sorted
+    fn unused(&self) -> Vec<&'a str> {
+        let used = self.used.borrow();
+        let mut unused: Vec<_> = self.parameters.keys().map(String::as_str).filter(|key| !used.contains(key)).collect();
+        unused.sort();
+        unused
+    }
+}
+
+/// World generators by name, so they can be picked from settings, saved worlds and tools
+///
+/// Other crates can `register` generators of their own alongside the built in ones. Generators
+/// are built with the registry's `blocks`, so they can place blocks loaded from a file as well as
+/// the built in ones.
+pub struct GeneratorRegistry {
+    factories: BTreeMap<String, GeneratorFactory>,
+    blocks: BlockRegistry,
+}
+
+#[allow(dead_code)]
+impl GeneratorRegistry {
+    pub fn new(blocks: BlockRegistry) -> GeneratorRegistry {
+        GeneratorRegistry { factories: BTreeMap::new(), blocks }
+    }
+
+    /// A registry of the built in generators - `flat`, `pillars`, `natural` and `heightmap`
+    pub fn with_builtins(blocks: BlockRegistry) -> GeneratorRegistry {
+        let mut registry = GeneratorRegistry::new(blocks);
+        registry.register("flat", flat).unwrap();
+        registry.register("pillars", pillars).unwrap();
+        registry.register("natural", natural).unwrap();
+        registry.register("heightmap", heightmap).unwrap();
+        registry
+    }
+
+    /// Add a generator under `name`, which mustn't be taken already
+    pub fn register<F>(&mut self, name: &str, factory: F) -> Result<(), String>
+        where F: Fn(usize, &ParameterReader) -> Result<Box<dyn WorldGenerator>, String> + 'static {
+        if self.factories.contains_key(name) {
+            return Err(format!("a generator named {:?} is already registered", name));
+        }
+        self.factories.insert(name.to_owned(), Box::new(factory));
+        Ok(())
+    }
+
+    pub fn blocks(&self) -> &BlockRegistry {
+        &self.blocks
+    }
+
+    /// Names of every registered generator, sorted
+    pub fn names(&self) -> Vec<&str> {
+        self.factories.keys().map(String::as_str).collect()
+    }
+
+    /// Build the generator named `name`
+    pub fn create(&self, name: &str, seed: usize, parameters: &GeneratorParameters) -> Result<Box<dyn WorldGenerator>, String> {
+        let factory = self.factories.get(name)
+            .ok_or_else(|| format!("unknown generator {:?} - expected one of {:?}", name, self.names()))?;
+        let reader = ParameterReader::new(parameters, &self.blocks);
+        let generator = factory(seed, &reader).map_err(|e| format!("generator {}: {}", name, e))?;
+        let unused = reader.unused();
+        if !unused.is_empty() {
+            return Err(format!("generator {} doesn't take parameters {:?}", name, unused));
+        }
+        Ok(generator)
+    }
+}
+
+fn flat(_seed: usize, parameters: &ParameterReader) -> Result<Box<dyn WorldGenerator>, String> {
+    let mut generator = FlatWorldGenerator::from_preset(parameters.get_str("preset").unwrap_or(DEFAULT_PRESET), parameters.blocks())?;
+    parameters.configure_stages(generator.pipeline_mut())?;
+    Ok(Box::new(generator))
+}
+
+fn pillars(seed: usize, parameters: &ParameterReader) -> Result<Box<dyn WorldGenerator>, String> {
+    let mut generator = RandomPillarsWorldGenerator::new(seed);
+    parameters.configure_stages(generator.pipeline_mut())?;
+    Ok(Box::new(generator))
+}
+
+fn natural(seed: usize, parameters: &ParameterReader) -> Result<Box<dyn WorldGenerator>, String> {
+    let mut natural = NaturalParameters::default();
+    natural.amplitude = parameters.get("amplitude", natural.amplitude)?;
+    natural.stone_height = parameters.get("stone_height", natural.stone_height)?;
+    natural.dirt_depth = parameters.get("dirt_depth", natural.dirt_depth)?;
+    natural.sea_level = parameters.get("sea_level", natural.sea_level)?;
+    natural.beach_height = parameters.get("beach_height", natural.beach_height)?;
+    natural.tree_density = parameters.get("tree_density", natural.tree_density)?;
+    let noise = &mut natural.noise;
+    noise.frequency = parameters.get("frequency", noise.frequency)?;
+    noise.octaves = parameters.get("octaves", noise.octaves)?;
+    noise.lacunarity = parameters.get("lacunarity", noise.lacunarity)?;
+    noise.persistence = parameters.get("persistence", noise.persistence)?;
+    noise.ridged = parameters.get("ridged", noise.ridged)?;
+    noise.warp_strength = parameters.get("warp_strength", noise.warp_strength)?;
+    noise.warp_frequency = parameters.get("warp_frequency", noise.warp_frequency)?;
+    if let Some(points) = parameters.get_str("height_curve") {
+        noise.height_curve = HeightCurve::parse(points)?;
+    }
+    if !parameters.get("caves", true)? {
+        natural.caves = None;
+    }
+    if !parameters.get("ores", true)? {
+        natural.ores = Vec::new();
+    }
+    let mut generator = NaturalWorldGenerator::with_parameters(seed, natural);
+    parameters.configure_stages(generator.pipeline_mut())?;
+    Ok(Box::new(generator))
+}
+
+fn heightmap(_seed: usize, parameters: &ParameterReader) -> Result<Box<dyn WorldGenerator>, String> {
+    let defaults = HeightmapParameters::default();
+    let edges = match parameters.get_str("edges") {
+        Some("tile") => EdgeMode::Tile,
+        Some("clamp") | None => EdgeMode::Clamp,
+        Some(other) => return Err(format!("edges must be \"tile\" or \"clamp\", not {:?}", other)),
+    };
+    let heightmap = HeightmapParameters {
+        horizontal_scale: parameters.get("horizontal_scale", defaults.horizontal_scale)?,
+        vertical_scale: parameters.get("vertical_scale", defaults.vertical_scale)?,
+        base_height: parameters.get("base_height", defaults.base_height)?,
+        edges,
+        dirt_depth: parameters.get("dirt_depth", defaults.dirt_depth)?,
+    };
+    if heightmap.horizontal_scale <= 0.0 || heightmap.horizontal_scale.is_nan() {
+        return Err(format!("horizontal_scale must be positive, not {}", heightmap.horizontal_scale));
+    }
+    let path = parameters.get_str("path").ok_or("missing parameter path - the heightmap image to load")?;
+    let mut generator = HeightmapWorldGenerator::open(path, parameters.get_str("colours"), heightmap, parameters.blocks())?;
+    parameters.configure_stages(generator.pipeline_mut())?;
+    Ok(Box::new(generator))
+}
+
+#[cfg(test)]
+mod tests {
+    use block;
+    use block_registry::BlockRegistry;
+    use world::{Chunk, ChunkCoordinates, HashChunk};
+    use worldgen::{WorldGenerator, FlatWorldGenerator};
+    use worldgen::registry::{GeneratorRegistry, GeneratorParameters};
+
+    fn parameters(pairs: &[(&str, &str)]) -> GeneratorParameters {
+        pairs.iter().map(|&(key, value)| (key.to_owned(), value.to_owned())).collect()
+    }
+
+    #[test]
+    fn registry_creates_builtins_by_name() {
+        let registry = GeneratorRegistry::with_builtins(BlockRegistry::default());
+        assert_eq!(registry.names(), vec!["flat", "heightmap", "natural", "pillars"]);
+        let mut flat = registry.create("flat", 0, &parameters(&[("preset", "2*sand;5")])).unwrap();
+        let chunk = flat.generate_chunk([0, 0, 0].into());
+        assert_eq!(chunk.get([0, 4, 0].into()), Some(&block::SAND));
+        assert_eq!(chunk.get([0, 5, 0].into()), None);
+
+        let mut natural = registry.create("natural", 42, &parameters(&[("caves", "false"), ("stages", "terrain")])).unwrap();
+        let chunk = natural.generate_chunk([0, -1, 0].into());
+        assert!(chunk.blocks.values().all(|&block_type| block_type == block::STONE || block_type == block::WATER));
+    }
+
+    #[test]
+    fn registry_generators_use_loaded_blocks() {
+        let mut blocks = BlockRegistry::default();
+        blocks.parse("[[blocks]]\nname = \"marble\"\ncolor = [0.9, 0.9, 0.95]\n").unwrap();
+        let registry = GeneratorRegistry::with_builtins(blocks);
+        let mut flat = registry.create("flat", 0, &parameters(&[("preset", "stone,marble;2")])).unwrap();
+        let chunk = flat.generate_chunk([0, 0, 0].into());
+        assert_eq!(chunk.get([0, 1, 0].into()).map(|block_type| block_type.name), Some("marble"));
+        assert!(GeneratorRegistry::with_builtins(BlockRegistry::default()).create("flat", 0, &parameters(&[("preset", "marble")])).is_err());
+    }
+
+    #[test]
+    fn registry_errors() {
+        let registry = GeneratorRegistry::with_builtins(BlockRegistry::default());
+        assert!(registry.create("marble", 0, &parameters(&[])).is_err());
+        assert!(registry.create("natural", 0, &parameters(&[("sea_level", "deep")])).is_err());
+        assert!(registry.create("natural", 0, &parameters(&[("sea_lvl", "4")])).is_err());
+        assert!(registry.create("natural", 0, &parameters(&[("height_curve", "0.5:1,-0.5:0")])).is_err());
+        assert!(registry.create("natural", 0, &parameters(&[("stages", "terrain,lava")])).is_err());
+        assert!(registry.create("heightmap", 0, &parameters(&[])).is_err());
+        let zero_scale = registry.create("heightmap", 0, &parameters(&[("path", "map.png"), ("horizontal_scale", "0")]));
+        assert_eq!(zero_scale.err(), Some("generator heightmap: horizontal_scale must be positive, not 0".to_owned()));
+    }
+
+    #[test]
+    fn registry_third_party_generators() {
+        struct Empty;
+        impl WorldGenerator for Empty {
+            fn generate_chunk(&mut self, _coordinates: ChunkCoordinates) -> HashChunk {
+                HashChunk::new()
+            }
+        }
+
+        let mut registry = GeneratorRegistry::with_builtins(BlockRegistry::default());
+        registry.register("empty", |_, _| Ok(Box::new(Empty))).unwrap();
+        assert!(registry.register("flat", |_, _| Ok(Box::new(FlatWorldGenerator::new()))).is_err());
+        let mut empty = registry.create("empty", 0, &parameters(&[])).unwrap();
+        assert!(empty.generate_chunk([0, 0, 0].into()).blocks.is_empty());
+        // parameters a generator doesn't ask for are still rejected
+        assert!(registry.create("empty", 0, &parameters(&[("size", "3")])).is_err());
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::{HashMap, HashSet};
+use world::{ChunkCoordinates, BlockCoordinates, HashChunk, Chunk, CHUNK_SIZE};
+use worldgen::chunk_seed;
+use worldgen::pipeline::{Stage, StageKind};
+use block;
+use block::BlockType;
+
+/// Something bigger than a single block, which may spill over chunk borders - e.g. a tree
+///
+/// A structure must not reach further than `CHUNK_SIZE` blocks from the column it's rooted in, so
+/// that it only ever spills into the neighbouring chunk columns.
+pub trait Structure {
+    /// Queue the blocks of this structure
+    fn place(&self, writes: &mut PendingWrites);
+}
+
+/// A trunk of wood topped by a ball of leaves
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tree {
+    /// World coordinates of the bottom of the trunk, just above the ground
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    /// Height of the trunk
+    pub height: i32,
+}
+
+impl Tree {
+    /// A tree growing on the ground at world (x, y - 1, z), between 4 and 6 blocks tall
+    pub fn new(seed: usize, x: i32, y: i32, z: i32) -> Tree {
+        let height = 4 + (chunk_seed(seed, [x, y, z].into()) % 3) as i32;
+        Tree { x, y, z, height }
+    }
+}
+
+impl Structure for Tree {
+    fn place(&self, writes: &mut PendingWrites) {
+        let top = self.y + self.height - 1;
+        // two wide layers of leaves around the top of the trunk, and two narrow ones above
+        for dy in -2..2 {
+            let radius: i32 = if dy < 0 { 2 } else { 1 };
+            for dx in -radius..radius + 1 {
+                for dz in -radius..radius + 1 {
+                    // trim the corners so the canopy is rounder
+                    if dx.abs() == radius && dz.abs() == radius && (radius == 2 || dy == 1) {
+                        continue;
+                    }
+                    writes.write(self.x + dx, top + dy, self.z + dz, block::LEAVES);
+                }
+            }
+        }
+        for y in self.y..top + 1 {
+            writes.write(self.x, y, self.z, block::WOOD);
+        }
+    }
+}
+
+/// Which block wins where two structures overlap - wood is never cut by another tree's leaves,
+/// and anything else is settled by name, so it doesn't matter which structure was placed first
+fn priority(block_type: &BlockType) -> (u8, &'static str) {
+    (if block_type == block::WOOD { 1 } else { 0 }, block_type.name)
+}
+
+/// Chunk columns further than this from the chunk being generated are forgotten - twice the
+/// largest render distance, so nothing around the loaded chunks is planned twice
+const KEEP_DISTANCE: i32 = 34;
+
+/// Blocks queued by structures, waiting for the chunks they fall in to be generated
+///
+/// Structures are planned a whole chunk column at a time, and every column neighbouring a chunk
+/// is planned before that chunk's writes are applied - so each chunk gets every block spilling
+/// into it from its neighbours, whichever order chunks are generated in. Columns far from where
+/// chunks are being generated are forgotten, and planned again if they're ever needed.
+pub struct PendingWrites {
+    writes: HashMap<ChunkCoordinates, HashMap<BlockCoordinates, &'static BlockType>>,
+    /// Chunk columns (x, z) whose structures have already been queued
+    planned: HashSet<(i32, i32)>,
+}
+
+impl PendingWrites {
+    pub fn new() -> PendingWrites {
+        PendingWrites { writes: HashMap::new(), planned: HashSet::new() }
+    }
+
+    /// Chunk columns (x, z) around (and including) the chunk at `coordinates` whose structures
+    /// haven't been planned yet - they're marked as planned, so the caller must place them
+    pub fn unplanned_around(&mut self, coordinates: ChunkCoordinates) -> Vec<(i32, i32)> {
+        let mut unplanned = Vec::new();
+        for x in coordinates[0] - 1..coordinates[0] + 2 {
+            for z in coordinates[2] - 1..coordinates[2] + 2 {
+                if self.planned.insert((x, z)) {
+                    unplanned.push((x, z));
+                }
+            }
+        }
+        unplanned
+    }
+
+    /// Forget the plans and writes of chunk columns more than KEEP_DISTANCE from the column at
+    /// (x, z) - writes are kept one column further than plans, so any column whose writes are
+    /// forgotten has no planned neighbours and gets every write back when it's planned again
+    pub fn forget_far_from(&mut self, x: i32, z: i32) {
+        let distance = |column_x: i32, column_z: i32| (column_x - x).abs().max((column_z - z).abs());
+        self.planned.retain(|&(column_x, column_z)| distance(column_x, column_z) <= KEEP_DISTANCE);
+        self.writes.retain(|coordinates, _| distance(coordinates[0], coordinates[2]) <= KEEP_DISTANCE + 1);
+    }
+
+    /// Queue a block at world (x, y, z) - where structures overlap, the result doesn't depend on
+    /// which was written first
+    pub fn write(&mut self, x: i32, y: i32, z: i32, block_type: &'static BlockType) {
+        let size = CHUNK_SIZE as i32;
+        let coordinates = [x.div_euclid(size), y.div_euclid(size), z.div_euclid(size)].into();
+        let position = [x.rem_euclid(size) as u8, y.rem_euclid(size) as u8, z.rem_euclid(size) as u8].into();
+        let queued = self.writes.entry(coordinates).or_default().entry(position).or_insert(block_type);
+        if priority(block_type) > priority(queued) {
+            *queued = block_type;
+        }
+    }
+
+    /// Set the blocks queued for the chunk at `coordinates` - structures only replace air and
+    /// replaceable blocks, so the rest of the terrain is left alone
+    pub fn apply(&mut self, coordinates: ChunkCoordinates, chunk: &mut HashChunk) {
+        if let Some(writes) = self.writes.remove(&coordinates) {
+            for (position, block_type) in writes {
+                if chunk.get(position).is_none_or(|existing| existing.replaceable) {
+                    chunk.set(position, block_type);
+                }
+            }
+        }
+    }
+}
+
+/// Lists the structures rooted in the chunk column at (x, z)
+pub type Planner = Box<dyn Fn(i32, i32) -> Vec<Box<dyn Structure>>>;
+
+/// Places the structures planned for each chunk column, as chunks are generated
+pub struct StructureStage {
+    name: &'static str,
+    writes: PendingWrites,
+    plan: Planner,
+}
+
+impl StructureStage {
+    pub fn new<F>(name: &'static str, plan: F) -> StructureStage where F: Fn(i32, i32) -> Vec<Box<dyn Structure>> + 'static {
+        StructureStage { name, writes: PendingWrites::new(), plan: Box::new(plan) }
+    }
+}
+
+impl Stage for StructureStage {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn kind(&self) -> StageKind {
+        StageKind::Structure
+    }
+
+    fn apply(&mut self, coordinates: ChunkCoordinates, chunk: &mut HashChunk) {
+        for (x, z) in self.writes.unplanned_around(coordinates) {
+            for structure in (self.plan)(x, z) {
+                structure.place(&mut self.writes);
+            }
+        }
+        self.writes.apply(coordinates, chunk);
+        self.writes.forget_far_from(coordinates[0], coordinates[2]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use block;
+    use world::{HashChunk, Chunk, CHUNK_SIZE};
+    use worldgen::structures::{PendingWrites, Structure, Tree, KEEP_DISTANCE};
+
+    #[test]
+    fn structures_overlap_in_any_order() {
+        let trees = [Tree { x: 0, y: 1, z: 0, height: 4 }, Tree { x: 2, y: 2, z: 1, height: 6 }];
+        let mut forwards = PendingWrites::new();
+        trees[0].place(&mut forwards);
+        trees[1].place(&mut forwards);
+        let mut backwards = PendingWrites::new();
+        trees[1].place(&mut backwards);
+        trees[0].place(&mut backwards);
+        assert!(forwards.writes == backwards.writes);
+        // the second tree's trunk cuts through the first's leaves
+        assert_eq!(forwards.writes[&[0, 0, 0].into()][&[2, 3, 1].into()], block::WOOD);
+    }
+
+    #[test]
+    fn structures_spill_into_neighbouring_chunks() {
+        let mut writes = PendingWrites::new();
+        Tree { x: 0, y: 0, z: 5, height: 4 }.place(&mut writes);
+        let mut west = HashChunk::new();
+        writes.apply([-1, 0, 0].into(), &mut west);
+        assert_eq!(west.get([CHUNK_SIZE - 1, 3, 5].into()), Some(&block::LEAVES));
+        assert_eq!(west.get([CHUNK_SIZE - 2, 2, 5].into()), Some(&block::LEAVES));
+
+        // terrain already in the chunk isn't replaced, unless it's replaceable
+        let mut east = HashChunk::new();
+        east.set([1, 3, 5].into(), block::STONE);
+        east.set([1, 2, 5].into(), block::WATER);
+        writes.apply([0, 0, 0].into(), &mut east);
+        assert_eq!(east.get([0, 3, 5].into()), Some(&block::WOOD));
+        assert_eq!(east.get([1, 3, 5].into()), Some(&block::STONE));
+        assert_eq!(east.get([1, 2, 5].into()), Some(&block::LEAVES));
+        assert!(writes.writes.is_empty());
+    }
+
+    #[test]
+    fn structures_forget_far_columns() {
+        let mut writes = PendingWrites::new();
+        assert_eq!(writes.unplanned_around([0, 0, 0].into()).len(), 9);
+        Tree { x: 0, y: 0, z: 5, height: 4 }.place(&mut writes);
+        writes.forget_far_from(KEEP_DISTANCE, 0);
+        assert_eq!(writes.planned.len(), 6);
+        assert_eq!(writes.writes.len(), 2);
+
+        // once forgotten, columns are planned again
+        writes.forget_far_from(KEEP_DISTANCE + 2, 0);
+        assert!(writes.planned.is_empty() && writes.writes.is_empty());
+        assert_eq!(writes.unplanned_around([0, 0, 0].into()).len(), 9);
+    }
+}